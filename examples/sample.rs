@@ -0,0 +1,628 @@
+use geo::Polygon;
+use glam::{vec2, vec3, vec4, Vec2};
+use miniquad::*;
+use quad_gl::*;
+
+use drawing::{draw_window, DrawContext};
+use megaui::{hash, InputHandler};
+use mq_sample::{
+    geometry::{gen_line_indices_closed, triangulate, ShadowPoint},
+    gfx::{gi::GiRenderer, shadow::ShadowMode},
+    scene::{
+        random_occluders, vec2_from_desc, SceneDesc, SdfShapeDesc, TextureQuadDesc,
+    },
+    sdf::SdfShape,
+    sprite::{Image, Sprite, TextureId},
+    Camera, Editor, Light, Occluder, SdfRenderer, ShadowRenderer, TextureRenderer, Transform2D,
+    World,
+};
+
+const POLYS_N: usize = 4;
+/// Index of the light which follows the mouse
+const CURSOR_LIGHT: usize = 0;
+/// Physics time step at speed 1
+const DT: f32 = 1. / 60.;
+/// Screen rect of the gui window, clicks on it don't reach the editor
+const GUI_POSITION: (f32, f32) = (0.5, 0.5);
+const GUI_SIZE: (f32, f32) = (250., 250.);
+/// Radians per second of Q/E camera rotation
+const CAMERA_ROTATION_SPEED: f32 = 1.;
+/// Screen distance at which the editor grabs vertices
+const PICK_PIXELS: f32 = 8.;
+/// Edited scene is saved here if no scene file is given
+const EDITED_SCENE: &str = "scenes/edited.json";
+/// Image of scene textures, decoded at runtime like any loaded bytes
+const ROBOT_PNG: &[u8] = include_bytes!("../vintage-robot.png");
+/// Slopes of the robot's normal map made from its brightness
+const ROBOT_RELIEF: f32 = 4.;
+
+pub struct Stage {
+    gl: QuadGl,
+    world: World,
+    camera: Camera,
+    mouse_pos: Vec2,
+    /// Mouse position in screen coords, `mouse_pos` follows the camera
+    mouse_screen: Vec2,
+    /// Held camera keys
+    camera_keys: Vec<KeyCode>,
+    /// Last mouse position of middle button drag
+    drag_screen: Option<Vec2>,
+    /// Occluder followed by the camera
+    follow: Option<usize>,
+    shadow_renderer: ShadowRenderer,
+    texture_renderer: TextureRenderer,
+    robot: TextureId,
+    /// Robots of the scene, a corner of each is moved by `robot_time`
+    robots: Vec<TextureQuadDesc>,
+    robot_time: f32,
+    sdf_renderer: SdfRenderer,
+    gi_renderer: GiRenderer,
+    /// Emissive surfaces light the scene
+    gi: bool,
+    drawing: DrawContext,
+    speed_mult: f32,
+    debug_drawing: bool,
+    editor: Editor,
+    /// Physics is paused and mouse edits occluders
+    edit_mode: bool,
+    /// Result of the last "save scene", shown under the button
+    save_status: Option<String>,
+}
+
+fn main() {
+    miniquad::start(conf::Conf{sample_count: 4, ..Default::default()}, |mut ctx| {
+        miniquad::UserData::owning(Stage::new(&mut ctx), ctx)
+    });
+}
+
+impl Stage {
+    pub fn new(ctx: &mut Context) -> Self {
+        let screen_size = ctx.screen_size();
+        let shadow_renderer = ShadowRenderer::new(ctx);
+        let mut texture_renderer = TextureRenderer::new(ctx);
+        let robot_image = Image::from_png(ROBOT_PNG).unwrap();
+        let robot = texture_renderer.add_image(ctx, &robot_image);
+        let robot_normals = robot_image.normal_map(ROBOT_RELIEF);
+        let normal_map = Texture::from_rgba8(
+            ctx,
+            robot_normals.width as u16,
+            robot_normals.height as u16,
+            &robot_normals.rgba,
+        );
+        texture_renderer.set_normal_map(robot, Some(normal_map));
+        let sdf_renderer = SdfRenderer::new(ctx);
+        let gi_renderer = GiRenderer::new(ctx);
+
+        let mut stage = Stage {
+            gl: QuadGl::new(ctx),
+            world: World::default(),
+            camera: Camera::new(screen_size.0, screen_size.1),
+            mouse_pos: vec2(0., 0.),
+            mouse_screen: vec2(0., 0.),
+            camera_keys: vec![],
+            drag_screen: None,
+            follow: None,
+            shadow_renderer,
+            texture_renderer,
+            robot,
+            robots: vec![],
+            robot_time: 0.,
+            sdf_renderer,
+            gi_renderer,
+            gi: false,
+            drawing: DrawContext::new(ctx),
+            speed_mult: 1.,
+            debug_drawing: false,
+            editor: Editor::default(),
+            edit_mode: false,
+            save_status: None,
+        };
+        stage.load_scene(ctx, &load_scene_file());
+        stage
+    }
+
+    /// Replace everything in the stage by the scene
+    pub fn load_scene(&mut self, ctx: &mut Context, scene: &SceneDesc) {
+        self.world = World::from_scene(scene);
+        self.editor.reset();
+        self.follow = None;
+        self.camera.bounds = Some(self.world.bounds);
+        if self.world.lights.is_empty() {
+            self.world.lights.push(Light::new(vec2(0., 0.), 1.));
+        }
+        self.robots = scene.textures.clone();
+        let shapes = scene.sdf_shapes.iter().map(SdfShape::from).collect();
+        if let Err(err) = self.sdf_renderer.set_shapes(ctx, shapes) {
+            panic!("can't draw sdf shapes of the scene: {:?}", err);
+        }
+    }
+
+    /// Everything in the stage as a scene
+    pub fn scene(&self) -> SceneDesc {
+        SceneDesc {
+            textures: self.robots.clone(),
+            sdf_shapes: self
+                .sdf_renderer
+                .shapes()
+                .iter()
+                .map(SdfShapeDesc::from)
+                .collect(),
+            ..SceneDesc::from_world(&self.world)
+        }
+    }
+
+    /// Draw imgui and update parameters
+    fn gui(&mut self, ctx: &mut Context) {
+        // udpate params from gui
+        let mut speed = self.speed_mult;
+        let mut th = self.shadow_renderer.shadow_smooth_th;
+        let mut shadow_mode = self.shadow_renderer.mode;
+        let mut gi = self.gi;
+        let mut light_size = self.world.lights[CURSOR_LIGHT].radius;
+        let mut ambient = self.world.ambient.x();
+        let resolution_scale = self.shadow_renderer.resolution_scale();
+        let mut new_resolution_scale = resolution_scale;
+        let mut debug_drawing = self.debug_drawing;
+        let mut random_scene = false;
+        let mut edit_mode = self.edit_mode;
+        let mut save_scene = false;
+        let save_status = self.save_status.as_deref();
+        let mut sdf_edge = self.sdf_renderer.sdf_edge;
+        let mut robo_transofrm_time = self.robot_time;
+        draw_window(
+            &mut self.drawing.ui,
+            hash!(),
+            vec2(GUI_POSITION.0, GUI_POSITION.1),
+            vec2(GUI_SIZE.0, GUI_SIZE.1),
+            None,
+            |ui| {
+                ui.label(None, "Controls");
+                if ui.button(None, "debug") {
+                    debug_drawing = !debug_drawing;
+                }
+                if ui.button(None, "random polygons") {
+                    random_scene = true;
+                }
+                if ui.button(None, if edit_mode { "stop editing" } else { "edit" }) {
+                    edit_mode = !edit_mode;
+                }
+                if ui.button(None, "save scene") {
+                    save_scene = true;
+                }
+                if let Some(status) = save_status {
+                    ui.label(None, status);
+                }
+                ui.slider(hash!(), "Speed", 0f32..10f32, &mut speed);
+                ui.slider(hash!(), "Shadow Border th", 0f32..1f32, &mut th);
+                let mode_label = match shadow_mode {
+                    ShadowMode::Geometry => "sdf shadows",
+                    ShadowMode::DistanceField => "geometry shadows",
+                };
+                if ui.button(None, mode_label) {
+                    shadow_mode = match shadow_mode {
+                        ShadowMode::Geometry => ShadowMode::DistanceField,
+                        ShadowMode::DistanceField => ShadowMode::Geometry,
+                    };
+                }
+                if ui.button(None, if gi { "no GI" } else { "GI" }) {
+                    gi = !gi;
+                }
+                ui.slider(hash!(), "Light_size", 0f32..3f32, &mut light_size);
+                ui.slider(hash!(), "Ambient", 0f32..1f32, &mut ambient);
+                ui.slider(
+                    hash!(),
+                    "Shadow resolution",
+                    0.1f32..2f32,
+                    &mut new_resolution_scale,
+                );
+                ui.slider(hash!(), "SDF edge", 0f32..1f32, &mut sdf_edge);
+                ui.slider(
+                    hash!(),
+                    "Robo transform",
+                    0f32..std::f32::consts::PI,
+                    &mut robo_transofrm_time,
+                );
+            },
+        );
+        self.speed_mult = speed;
+        self.shadow_renderer.shadow_smooth_th = th;
+        self.shadow_renderer.mode = shadow_mode;
+        self.gi = gi;
+        self.world.lights[CURSOR_LIGHT].radius = light_size;
+        self.world.ambient = vec3(ambient, ambient, ambient);
+        if (new_resolution_scale - resolution_scale).abs() > f32::EPSILON {
+            self.shadow_renderer
+                .set_resolution_scale(ctx, new_resolution_scale);
+        }
+        self.debug_drawing = debug_drawing;
+        if random_scene {
+            self.world.occluders = random_occluders(POLYS_N)
+                .iter()
+                .map(Occluder::from)
+                .collect();
+            self.editor.reset();
+            self.follow = None;
+            self.camera.add_trauma(0.6);
+        }
+        if edit_mode != self.edit_mode {
+            self.edit_mode = edit_mode;
+            self.editor.reset();
+        }
+        if save_scene {
+            self.save_status = Some(save_scene_file(&self.scene()));
+        }
+        self.sdf_renderer.sdf_edge = sdf_edge;
+        self.robot_time = robo_transofrm_time;
+    }
+}
+
+impl EventHandler for Stage {
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
+        if self.camera.update_window(width, height) {
+            let (width, height) = self.camera.window_size();
+            self.shadow_renderer.resize(ctx, width, height);
+            self.gi_renderer.resize(ctx, width, height);
+        }
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: KeyCode,
+        _keymods: KeyMods,
+        _repeat: bool,
+    ) {
+        let camera_key =
+            pan_key_direction(keycode).is_some() || rotation_key_direction(keycode).is_some();
+        if camera_key && !self.camera_keys.contains(&keycode) {
+            self.camera_keys.push(keycode);
+        }
+        if keycode == KeyCode::F {
+            // toggle following of the occluder under the mouse
+            self.follow = match self.follow {
+                Some(_) => None,
+                None => self.world.pick(self.mouse_pos),
+            };
+        }
+        if !self.edit_mode {
+            return;
+        }
+        match keycode {
+            KeyCode::Delete | KeyCode::Backspace => self.editor.delete(&mut self.world),
+            KeyCode::Enter => {
+                self.editor.finish_polygon(&mut self.world);
+            }
+            KeyCode::Escape => self.editor.reset(),
+            _ => {}
+        }
+    }
+
+    fn key_up_event(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymods: KeyMods) {
+        self.camera_keys.retain(|key| *key != keycode);
+    }
+
+    fn mouse_button_down_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        self.drawing.ui.mouse_down((x, y));
+        if button == MouseButton::Middle {
+            self.drag_screen = Some(vec2(x, y));
+        }
+        if !self.edit_mode || over_gui(x, y) {
+            return;
+        }
+        match button {
+            MouseButton::Left => {
+                let point = self.camera.unproject(vec2(x, y));
+                let grab_point = self.camera.unproject(vec2(x + PICK_PIXELS, y));
+                self.editor.pick_radius = (grab_point - point).length();
+                self.editor.mouse_down(&mut self.world, point);
+            }
+            MouseButton::Right => {
+                self.editor.finish_polygon(&mut self.world);
+            }
+            _ => {}
+        }
+    }
+
+    fn mouse_button_up_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        self.drawing.ui.mouse_up((x, y));
+        self.editor.mouse_up();
+        if button == MouseButton::Middle {
+            self.drag_screen = None;
+        }
+    }
+
+    fn mouse_motion_event(&mut self, _ctx: &mut Context, x: f32, y: f32) {
+        self.mouse_screen = vec2(x, y);
+        if let Some(last) = self.drag_screen.replace(self.mouse_screen) {
+            self.camera.drag(last, self.mouse_screen);
+        }
+        self.mouse_pos = self.camera.unproject(self.mouse_screen);
+        self.world.lights[CURSOR_LIGHT].position = self.mouse_pos;
+        self.drawing.ui.mouse_move((x, y));
+        if self.edit_mode {
+            self.editor.mouse_move(&mut self.world, self.mouse_pos);
+        }
+    }
+
+    fn mouse_wheel_event(&mut self, _ctx: &mut Context, x: f32, y: f32) {
+        self.drawing.ui.mouse_wheel(x, y);
+        self.camera.zoom_at(y, self.mouse_screen);
+    }
+
+    fn update(&mut self, _ctx: &mut Context) {
+        let direction = self
+            .camera_keys
+            .iter()
+            .filter_map(|key| pan_key_direction(*key))
+            .fold(vec2(0., 0.), |sum, d| sum + d);
+        self.camera.pan_direction(direction, DT);
+        let rotation: f32 = self
+            .camera_keys
+            .iter()
+            .filter_map(|key| rotation_key_direction(*key))
+            .sum();
+        self.camera.rotation += rotation * CAMERA_ROTATION_SPEED * DT;
+        if let Some(occluder) = self.follow.and_then(|i| self.world.occluders.get(i)) {
+            self.camera.follow(occluder.transform.translation);
+        }
+        self.camera.update(DT);
+        // the world point under the cursor changes when the camera moves
+        self.mouse_pos = self.camera.unproject(self.mouse_screen);
+        self.world.lights[CURSOR_LIGHT].position = self.mouse_pos;
+        if !self.edit_mode {
+            self.world.update(self.speed_mult * DT);
+        }
+        self.world.compute_shadows();
+    }
+
+    fn draw(&mut self, ctx: &mut Context) {
+        self.gui(ctx);
+        // floor color, it is multiplied by lights later
+        ctx.begin_default_pass(PassAction::clear_color(0.7, 0.7, 0.7, 1.));
+        self.gl.set_projection_matrix(self.camera.get_projection());
+        for occluder in self.world.occluders.iter() {
+            draw_polygon(&mut self.gl, &occluder.polygon, &occluder.transform);
+        }
+        // highlight occluder under the mouse
+        if let Some(i) = self.world.pick(self.mouse_pos) {
+            let polygon = self.world.occluders[i].placed_polygon();
+            draw_polygon_outline(&mut self.gl, &polygon, WHITE);
+        }
+        if self.edit_mode {
+            self.draw_editor();
+        }
+        if self.debug_drawing {
+            debug_drawing(
+                &mut self.gl,
+                self.mouse_pos,
+                self.world.shadows(CURSOR_LIGHT),
+            );
+            let visible = self.world.visibility(self.mouse_pos);
+            draw_polygon_outline(&mut self.gl, &visible, YELLOW);
+        }
+        ctx.end_render_pass();
+
+        let projection = self.camera.get_projection();
+        self.texture_renderer.sprites = self
+            .robots
+            .iter()
+            .map(|quad| robot_sprite(self.robot, quad, self.robot_time))
+            .collect();
+        self.texture_renderer.update(ctx);
+        self.texture_renderer.draw(ctx, projection);
+        self.sdf_renderer.draw(ctx, projection);
+
+        let projection = self.camera.get_projection();
+        self.shadow_renderer.reconstruct_buffers(ctx, &self.world);
+        // robot relief is lit by every light
+        self.shadow_renderer.clear_normals(ctx);
+        self.texture_renderer
+            .draw_normals(ctx, projection, self.shadow_renderer.normal_pass());
+        if self.gi {
+            self.gi_renderer
+                .update(ctx, &self.world, self.sdf_renderer.shapes());
+            self.gi_renderer.draw(ctx, projection);
+            self.shadow_renderer
+                .set_irradiance(Some(self.gi_renderer.irradiance_img()));
+        } else {
+            self.shadow_renderer.set_irradiance(None);
+        }
+        self.shadow_renderer.draw(ctx, &self.world, projection);
+
+        self.gl.draw(ctx);
+        self.drawing.update_projection_matrix(ctx);
+        self.drawing.perform_render_passes(ctx);
+        ctx.commit_frame();
+    }
+}
+
+impl Stage {
+    /// Selected occluder, vertices and the polygon being placed
+    fn draw_editor(&mut self) {
+        for occluder in self.world.occluders.iter() {
+            let polygon = occluder.placed_polygon();
+            let vertices: Vec<_> = polygon
+                .exterior()
+                .points_iter()
+                .chain(
+                    polygon
+                        .interiors()
+                        .iter()
+                        .flat_map(|ring| ring.points_iter()),
+                )
+                .map(|p| vec2(p.x(), p.y()))
+                .collect();
+            draw_points(&mut self.gl, &vertices, self.editor.pick_radius, WHITE);
+        }
+        if let Some(occluder) = self
+            .editor
+            .selected
+            .and_then(|i| self.world.occluders.get(i))
+        {
+            draw_polygon_outline(&mut self.gl, &occluder.placed_polygon(), GREEN);
+        }
+        if !self.editor.new_polygon.is_empty() {
+            let mut points = self.editor.new_polygon.clone();
+            points.push(self.mouse_pos);
+            draw_polyline(&mut self.gl, &points, GREEN);
+            draw_points(
+                &mut self.gl,
+                &self.editor.new_polygon,
+                self.editor.pick_radius,
+                GREEN,
+            );
+        }
+    }
+}
+
+/// Scene from the file given in command line or the default one
+fn load_scene_file() -> SceneDesc {
+    let path = match std::env::args().nth(1) {
+        Some(path) => path,
+        None => return SceneDesc::default_scene(),
+    };
+    match std::fs::read_to_string(&path).map(|json| SceneDesc::from_json(&json)) {
+        Ok(Ok(scene)) => scene,
+        Ok(Err(err)) => panic!("can't parse scene {}: {}", path, err),
+        Err(err) => panic!("can't read scene {}: {}", path, err),
+    }
+}
+
+/// Write the scene to the file given in command line or to `EDITED_SCENE`, returns the status for the gui
+fn save_scene_file(scene: &SceneDesc) -> String {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| EDITED_SCENE.to_string());
+    let saved = scene
+        .to_json()
+        .map_err(|err| err.to_string())
+        .and_then(|json| std::fs::write(&path, json).map_err(|err| err.to_string()));
+    match saved {
+        Ok(()) => format!("saved to {}", path),
+        Err(err) => format!("can't save {}: {}", path, err),
+    }
+}
+
+/// Panning direction of WASD and arrow keys
+fn pan_key_direction(keycode: KeyCode) -> Option<Vec2> {
+    match keycode {
+        KeyCode::W | KeyCode::Up => Some(vec2(0., 1.)),
+        KeyCode::S | KeyCode::Down => Some(vec2(0., -1.)),
+        KeyCode::A | KeyCode::Left => Some(vec2(-1., 0.)),
+        KeyCode::D | KeyCode::Right => Some(vec2(1., 0.)),
+        _ => None,
+    }
+}
+
+/// Camera rotation direction of Q and E keys
+fn rotation_key_direction(keycode: KeyCode) -> Option<f32> {
+    match keycode {
+        KeyCode::Q => Some(1.),
+        KeyCode::E => Some(-1.),
+        _ => None,
+    }
+}
+
+/// Screen point is inside the gui window
+fn over_gui(x: f32, y: f32) -> bool {
+    x >= GUI_POSITION.0
+        && y >= GUI_POSITION.1
+        && x <= GUI_POSITION.0 + GUI_SIZE.0
+        && y <= GUI_POSITION.1 + GUI_SIZE.1
+}
+
+/// draw shadows mesh
+fn debug_drawing(gl: &mut QuadGl, mouse_pos: Vec2, shadow_points: &[ShadowPoint]) {
+    gl.draw_mode(DrawMode::Lines);
+    for triangle in shadow_points.chunks(3) {
+        let geom: Vec<_> = triangle
+            .iter()
+            .map(|p| Vertex::new(p.pos.x(), p.pos.y(), 0., 0., 0., BLUE))
+            .collect();
+        gl.geometry(&geom, &gen_line_indices_closed(geom.len() as u16));
+    }
+    // draw triangle under mouse
+    gl.draw_mode(DrawMode::Triangles);
+    let pointer_size = 0.1;
+    let (mx, my) = (mouse_pos.x(), mouse_pos.y());
+    gl.geometry(
+        &[
+            Vertex::new(mx, my - pointer_size, 0., 0., 0., BLUE),
+            Vertex::new(mx + pointer_size, my + pointer_size, 0., 0., 0., RED),
+            Vertex::new(mx - pointer_size, my + pointer_size, 0., 0., 0., GREEN),
+        ],
+        &[0, 1, 2],
+    );
+}
+
+/// Robot sprite of a scene texture quad, one corner is moved by time, the image is mirrored to look to the left
+fn robot_sprite(robot: TextureId, quad: &TextureQuadDesc, time: f32) -> Sprite {
+    let (pos, size) = (vec2_from_desc(quad.position), quad.size);
+    let corners = [
+        pos + vec2(-size, -size),
+        pos + vec2(size, -size),
+        pos + vec2(size, f32::sin(time) * size),
+        pos + vec2(-1.5 * size, size),
+    ];
+    let [r, g, b, a] = quad.tint;
+    Sprite {
+        region: (vec2(1., 0.), vec2(0., 1.)),
+        tint: vec4(r, g, b, a),
+        ..Sprite::new(robot, corners)
+    }
+}
+
+/// Draw exterior of polygon with lines
+fn draw_polygon_outline(gl: &mut QuadGl, poly: &Polygon<f32>, color: Color) {
+    gl.draw_mode(DrawMode::Lines);
+    let exterior: Vec<_> = poly
+        .exterior()
+        .points_iter()
+        .map(|p| Vertex::new(p.x(), p.y(), 0., 0., 0., color))
+        .collect();
+    gl.geometry(&exterior, &gen_line_indices_closed(exterior.len() as u16));
+}
+
+/// Draw open line through the points
+fn draw_polyline(gl: &mut QuadGl, points: &[Vec2], color: Color) {
+    gl.draw_mode(DrawMode::Lines);
+    let vertices: Vec<_> = points
+        .iter()
+        .map(|p| Vertex::new(p.x(), p.y(), 0., 0., 0., color))
+        .collect();
+    let indices: Vec<u16> = (1..points.len() as u16)
+        .flat_map(|i| vec![i - 1, i])
+        .collect();
+    gl.geometry(&vertices, &indices);
+}
+
+/// Draw squares of given half size around the points
+fn draw_points(gl: &mut QuadGl, points: &[Vec2], half_size: f32, color: Color) {
+    gl.draw_mode(DrawMode::Triangles);
+    for p in points.iter() {
+        let (x, y, s) = (p.x(), p.y(), half_size);
+        gl.geometry(
+            &[
+                Vertex::new(x - s, y - s, 0., 0., 0., color),
+                Vertex::new(x + s, y - s, 0., 0., 0., color),
+                Vertex::new(x + s, y + s, 0., 0., 0., color),
+                Vertex::new(x - s, y + s, 0., 0., 0., color),
+            ],
+            &[0, 1, 2, 0, 2, 3],
+        );
+    }
+}
+
+/// Draw inner size of polygon
+fn draw_polygon(gl: &mut QuadGl, poly: &Polygon<f32>, transform: &Transform2D) {
+    gl.draw_mode(DrawMode::Triangles);
+    let (vertices, indices) = triangulate(poly);
+    let vertices: Vec<_> = vertices
+        .iter()
+        .map(|p| {
+            let p = transform.apply(*p);
+            Vertex::new(p.x(), p.y(), 0., 0., 0., Color([50, 50, 50, 255]))
+        })
+        .collect();
+    gl.geometry(&vertices, &indices);
+}
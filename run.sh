@@ -1,2 +1,2 @@
-cargo build --release --target wasm32-unknown-unknown
-cp target/wasm32-unknown-unknown/release/mq_sample.wasm mq_sample.wasm
\ No newline at end of file
+cargo build --release --example sample --target wasm32-unknown-unknown
+cp target/wasm32-unknown-unknown/release/examples/sample.wasm mq_sample.wasm
\ No newline at end of file
@@ -1,14 +1,55 @@
 use glam::{vec2, vec3, vec4, Mat4, Vec2};
+use std::cell::Cell;
 pub const MAX_ZOOM: f32 = 0.8;
 pub const MIN_ZOOM: f32 = 0.05;
 pub const INIT_ZOOM: f32 = 0.2;
+/// Default speed of approaching the target position and zoom, 1/s
+pub const DAMPING: f32 = 10.;
+/// Default keyboard panning speed, view widths per second
+pub const PAN_SPEED: f32 = 0.5;
+/// Default half size of the follow dead-zone, view widths
+pub const DEAD_ZONE: (f32, f32) = (0.1, 0.05);
+/// Default trauma lost per second
+pub const TRAUMA_DECAY: f32 = 1.;
+/// Default shake offset at full trauma, view widths
+pub const MAX_SHAKE: f32 = 0.03;
+
+/// State the matrices are built from
+#[derive(Clone, Copy, PartialEq)]
+struct ViewState {
+    center: Vec2,
+    zoom: f32,
+    rotation: f32,
+    window_width: f32,
+    window_height: f32,
+}
 
 /// Simple orthographic camera
+/// Position and zoom smoothly follow their targets in `update`
 pub struct Camera {
     pub position2d: Vec2,
     pub zoom: f32,
+    /// Counter clockwise rotation of the view, radians
+    pub rotation: f32,
+    pub target_position: Vec2,
+    pub target_zoom: f32,
+    /// Speed of approaching the targets, 1/s. Infinity disables smoothing
+    pub damping: f32,
+    pub pan_speed: f32,
+    /// Half size of the area around the view center where the followed point doesn't move the camera, view widths
+    pub dead_zone: Vec2,
+    /// (min, max) corners of the world area, the view never leaves it
+    pub bounds: Option<(Vec2, Vec2)>,
+    /// Shake strength in [0, 1], shake offset grows as its square
+    pub trauma: f32,
+    pub trauma_decay: f32,
+    pub max_shake: f32,
+    shake_time: f32,
+    shake_offset: Vec2,
     window_width: f32,
     window_height: f32,
+    /// Projection and its inverse, rebuilt when the view state changes
+    matrices: Cell<Option<(ViewState, Mat4, Mat4)>>,
 }
 
 #[allow(dead_code)]
@@ -17,35 +58,61 @@ impl Camera {
         Camera {
             position2d: vec2(0., 0.),
             zoom: INIT_ZOOM,
+            rotation: 0.,
+            target_position: vec2(0., 0.),
+            target_zoom: INIT_ZOOM,
+            damping: DAMPING,
+            pan_speed: PAN_SPEED,
+            dead_zone: vec2(DEAD_ZONE.0, DEAD_ZONE.1),
+            bounds: None,
+            trauma: 0.,
+            trauma_decay: TRAUMA_DECAY,
+            max_shake: MAX_SHAKE,
+            shake_time: 0.,
+            shake_offset: vec2(0., 0.),
             window_width,
             window_height,
+            matrices: Cell::new(None),
         }
     }
 
-    pub fn update_window(&mut self, width: f32, height: f32) {
+    /// Returns true if window size has changed
+    pub fn update_window(&mut self, width: f32, height: f32) -> bool {
+        let changed = (self.window_width, self.window_height) != (width, height);
         self.window_width = width;
         self.window_height = height;
+        changed
+    }
+
+    pub fn window_size(&self) -> (f32, f32) {
+        (self.window_width, self.window_height)
     }
 
     pub fn get_projection(&self) -> Mat4 {
-        let w = 1. / self.zoom;
-        let h = (self.window_height / self.window_width) / self.zoom;
-        let proj = Mat4::orthographic_rh_gl(
-            -w / 2., // left
-            w / 2.,  // right
-            -h / 2., // bottom
-            h / 2.,  // top
-            1.,      // near
-            0.,      // far
-        );
-        let eye = vec3(self.position2d.x(), self.position2d.y(), 1.);
-        let center = vec3(self.position2d.x(), self.position2d.y(), 0.0);
-        let up = vec3(0.0, 1.0, 0.0);
-        let view = Mat4::look_at_rh(eye, center, up);
-        proj * view
+        self.matrices().0
+    }
+
+    /// Projection and its inverse, cached until the view changes
+    fn matrices(&self) -> (Mat4, Mat4) {
+        let state = ViewState {
+            center: self.view_center(),
+            zoom: self.zoom,
+            rotation: self.rotation,
+            window_width: self.window_width,
+            window_height: self.window_height,
+        };
+        match self.matrices.get() {
+            Some((cached, projection, inverse)) if cached == state => (projection, inverse),
+            _ => {
+                let projection = build_projection(&state);
+                let inverse = projection.inverse();
+                self.matrices.set(Some((state, projection, inverse)));
+                (projection, inverse)
+            }
+        }
     }
 
-    /// Project into [0, 1] x [0, 1] space. NOTE It creates projection matrix inside
+    /// Project into [0, 1] x [0, 1] space
     pub fn project(&self, point: Vec2) -> Vec2 {
         let (width, height) = (self.window_width, self.window_height);
         let mvp = self.get_projection();
@@ -64,13 +131,356 @@ impl Camera {
         let sx = -1. + 2. * x / width;
         let sy = 1. - 2. * y / height;
         // apply inverse matrix to point on a surface
-        let unproject_pos = self.get_projection().inverse() * vec4(sx, sy, 0., 1.);
+        let unproject_pos = self.matrices().1 * vec4(sx, sy, 0., 1.);
         vec2(unproject_pos.x(), unproject_pos.y())
     }
 
-    /// Udpate zoom from wheel y diff
+    /// Udpate zoom from wheel y diff, zooms around the window center
     pub fn update_from_wheel(&mut self, value: f32) {
-        self.zoom *= f32::powf(1.2, value);
-        self.zoom = self.zoom.min(MAX_ZOOM).max(MIN_ZOOM);
+        let center = vec2(self.window_width / 2., self.window_height / 2.);
+        self.zoom_at(value, center);
+    }
+
+    /// Zoom from wheel y diff keeping the world point under the cursor(screen coords) in place
+    pub fn zoom_at(&mut self, value: f32, cursor: Vec2) {
+        let offset = self.view_offset(cursor);
+        let anchor = self.target_position + offset / self.target_zoom;
+        self.target_zoom *= f32::powf(1.2, value);
+        self.target_zoom = self.target_zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        self.target_position = anchor - offset / self.target_zoom;
+    }
+
+    /// Move the target in world coords
+    pub fn pan(&mut self, delta: Vec2) {
+        self.target_position += delta;
+    }
+
+    /// Keyboard panning, direction components are in [-1, 1] and follow the screen axes
+    pub fn pan_direction(&mut self, direction: Vec2, dt: f32) {
+        let direction = rotate(direction, self.rotation);
+        self.pan(direction * self.pan_speed * dt / self.target_zoom);
+    }
+
+    /// Mouse drag from one screen point to another, the world point under the cursor follows it
+    /// Applied without smoothing
+    pub fn drag(&mut self, from: Vec2, to: Vec2) {
+        let delta = (self.view_offset(from) - self.view_offset(to)) / self.zoom;
+        self.position2d += delta;
+        self.target_position += delta;
+        self.constrain();
+    }
+
+    /// Move the target so the point stays inside the dead-zone
+    pub fn follow(&mut self, point: Vec2) {
+        let half = self.dead_zone / self.target_zoom;
+        // the dead-zone is aligned with the screen
+        let offset = rotate(point - self.target_position, -self.rotation);
+        let excess = vec2(
+            dead_zone_excess(offset.x(), half.x()),
+            dead_zone_excess(offset.y(), half.y()),
+        );
+        self.target_position += rotate(excess, self.rotation);
+    }
+
+    /// Start shaking, trauma is clamped to 1
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).min(1.);
+    }
+
+    /// Move position and zoom towards the targets, decay the shake
+    pub fn update(&mut self, dt: f32) {
+        // no time passed -- no motion, infinite damping times zero dt would be NaN
+        let t = if dt > 0. {
+            1. - (-self.damping * dt).exp()
+        } else {
+            0.
+        };
+        self.position2d += (self.target_position - self.position2d) * t;
+        self.zoom += (self.target_zoom - self.zoom) * t;
+        self.constrain();
+
+        self.trauma = (self.trauma - self.trauma_decay * dt).max(0.);
+        self.shake_time += dt;
+        let time = self.shake_time;
+        // cheap deterministic wobble instead of noise
+        let wobble = vec2(
+            (time * 31.).sin() * (time * 17.).cos(),
+            (time * 23.).sin() * (time * 11.).cos(),
+        );
+        self.shake_offset = wobble * self.trauma * self.trauma * self.max_shake / self.zoom;
+    }
+
+    /// Jump to the targets
+    pub fn snap(&mut self) {
+        self.position2d = self.target_position;
+        self.zoom = self.target_zoom;
+        self.constrain();
     }
+
+    /// Center of the view with the shake, kept inside the bounds
+    pub fn view_center(&self) -> Vec2 {
+        self.clamp_center(self.position2d + self.shake_offset, self.zoom)
+    }
+
+    /// Keep the view of targets and current state inside the bounds
+    fn constrain(&mut self) {
+        let (min, max) = match self.bounds {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        // zoom in until the view fits
+        let size = max - min;
+        let extents = self.view_extents(1.);
+        let fit_zoom = (2. * extents.x() / size.x()).max(2. * extents.y() / size.y());
+        self.zoom = self.zoom.max(fit_zoom);
+        self.target_zoom = self.target_zoom.max(fit_zoom);
+        self.position2d = self.clamp_center(self.position2d, self.zoom);
+        self.target_position = self.clamp_center(self.target_position, self.target_zoom);
+    }
+
+    fn clamp_center(&self, center: Vec2, zoom: f32) -> Vec2 {
+        let (min, max) = match self.bounds {
+            Some(bounds) => bounds,
+            None => return center,
+        };
+        let half = self.view_extents(zoom);
+        let clamp = |x: f32, min: f32, max: f32, half: f32| {
+            if max - min < 2. * half {
+                (min + max) / 2.
+            } else {
+                x.max(min + half).min(max - half)
+            }
+        };
+        vec2(
+            clamp(center.x(), min.x(), max.x(), half.x()),
+            clamp(center.y(), min.y(), max.y(), half.y()),
+        )
+    }
+
+    /// World offset of the screen point from the window center in view widths(world units at zoom 1)
+    fn view_offset(&self, point: Vec2) -> Vec2 {
+        let (width, height) = (self.window_width, self.window_height);
+        let offset = vec2(
+            point.x() / width - 0.5,
+            (0.5 - point.y() / height) * height / width,
+        );
+        rotate(offset, self.rotation)
+    }
+
+    /// Half size of the world box around the (rotated) view
+    fn view_extents(&self, zoom: f32) -> Vec2 {
+        let half = vec2(0.5, 0.5 * self.window_height / self.window_width) / zoom;
+        let (sin, cos) = self.rotation.sin_cos();
+        vec2(
+            cos.abs() * half.x() + sin.abs() * half.y(),
+            sin.abs() * half.x() + cos.abs() * half.y(),
+        )
+    }
+}
+
+fn build_projection(state: &ViewState) -> Mat4 {
+    let w = 1. / state.zoom;
+    let h = (state.window_height / state.window_width) / state.zoom;
+    let proj = Mat4::orthographic_rh_gl(
+        -w / 2., // left
+        w / 2.,  // right
+        -h / 2., // bottom
+        h / 2.,  // top
+        1.,      // near
+        0.,      // far
+    );
+    let position = state.center;
+    let eye = vec3(position.x(), position.y(), 1.);
+    let center = vec3(position.x(), position.y(), 0.0);
+    let (sin, cos) = state.rotation.sin_cos();
+    let up = vec3(-sin, cos, 0.0);
+    let view = Mat4::look_at_rh(eye, center, up);
+    proj * view
+}
+
+/// Counter clockwise rotation
+fn rotate(v: Vec2, angle: f32) -> Vec2 {
+    let (sin, cos) = angle.sin_cos();
+    vec2(v.x() * cos - v.y() * sin, v.x() * sin + v.y() * cos)
+}
+
+/// Part of the offset outside of [-half, half]
+fn dead_zone_excess(offset: f32, half: f32) -> f32 {
+    if offset > half {
+        offset - half
+    } else if offset < -half {
+        offset + half
+    } else {
+        0.
+    }
+}
+
+#[test]
+fn zoom_keeps_cursor_point() {
+    let mut camera = Camera::new(800., 600.);
+    camera.position2d = vec2(1., -2.);
+    camera.target_position = camera.position2d;
+    let cursor = vec2(100., 450.);
+    let before = camera.unproject(cursor);
+    camera.zoom_at(2., cursor);
+    camera.snap();
+    assert!(camera.zoom > INIT_ZOOM);
+    assert!((camera.unproject(cursor) - before).length() < 1E-4);
+}
+
+#[test]
+fn drag_keeps_point_under_cursor() {
+    let mut camera = Camera::new(800., 600.);
+    let (from, to) = (vec2(300., 200.), vec2(420., 150.));
+    let grabbed = camera.unproject(from);
+    camera.drag(from, to);
+    assert!((camera.unproject(to) - grabbed).length() < 1E-4);
+    assert_eq!(camera.position2d, camera.target_position);
+}
+
+#[test]
+fn smoothing_approaches_target() {
+    let mut camera = Camera::new(800., 600.);
+    camera.pan_direction(vec2(1., 0.), 1.);
+    let target = camera.target_position;
+    assert!((target.x() - PAN_SPEED / INIT_ZOOM).abs() < 1E-5);
+    let mut distance = target.length();
+    for _ in 0..60 {
+        camera.update(1. / 60.);
+        let new_distance = (target - camera.position2d).length();
+        assert!(new_distance < distance);
+        distance = new_distance;
+    }
+    assert!(distance < 1E-3);
+    camera.damping = f32::INFINITY;
+    camera.pan(vec2(0., 1.));
+    camera.update(1. / 60.);
+    assert_eq!(camera.position2d, camera.target_position);
+}
+
+#[test]
+fn follow_dead_zone() {
+    let mut camera = Camera::new(800., 600.);
+    camera.damping = f32::INFINITY;
+    let center = vec2(400., 300.);
+    // inside the dead-zone the camera stays
+    let near = camera.unproject(center + vec2(50., -20.));
+    camera.follow(near);
+    camera.update(1. / 60.);
+    assert!((camera.project(near) - (center + vec2(50., -20.))).length() < 1E-3);
+    // far point ends up on the dead-zone border
+    let far = vec2(30., -20.);
+    camera.follow(far);
+    camera.update(1. / 60.);
+    let border = center + vec2(DEAD_ZONE.0, DEAD_ZONE.1) * 800.;
+    assert!((camera.project(far) - border).length() < 1E-2);
+}
+
+#[test]
+fn view_stays_in_bounds() {
+    let mut camera = Camera::new(800., 600.);
+    let (min, max) = (vec2(-4., -2.), vec2(6., 8.));
+    camera.bounds = Some((min, max));
+    let inside = |camera: &Camera| {
+        let corners = [vec2(0., 0.), vec2(800., 600.)];
+        corners.iter().all(|corner| {
+            let p = camera.unproject(*corner);
+            p.x() >= min.x() - 1E-4
+                && p.y() >= min.y() - 1E-4
+                && p.x() <= max.x() + 1E-4
+                && p.y() <= max.y() + 1E-4
+        })
+    };
+    camera.pan(vec2(-100., 50.));
+    for _ in 0..30 {
+        camera.update(1. / 60.);
+        assert!(inside(&camera));
+    }
+    camera.update_from_wheel(-20.);
+    camera.add_trauma(1.);
+    for _ in 0..30 {
+        camera.update(1. / 60.);
+        assert!(inside(&camera));
+    }
+    // the view fits into the bounds exactly when zoomed out
+    camera.snap();
+    assert!((camera.unproject(vec2(0., 0.)).x() - min.x()).abs() < 1E-4);
+    assert!((camera.unproject(vec2(800., 0.)).x() - max.x()).abs() < 1E-4);
+    camera.update_from_wheel(5.);
+    camera.snap();
+    camera.drag(vec2(0., 0.), vec2(5000., -5000.));
+    assert!(inside(&camera));
+}
+
+#[test]
+fn zero_dt_update() {
+    let mut camera = Camera::new(800., 600.);
+    camera.damping = f32::INFINITY;
+    camera.pan(vec2(1., 2.));
+    camera.update(0.);
+    assert_eq!(camera.position2d, vec2(0., 0.));
+    assert!(camera.unproject(vec2(400., 300.)).length() < 1E-5);
+    camera.update(1. / 60.);
+    assert_eq!(camera.position2d, vec2(1., 2.));
+}
+
+#[test]
+fn shake_decays() {
+    let mut camera = Camera::new(800., 600.);
+    let center = vec2(400., 300.);
+    camera.add_trauma(0.7);
+    camera.add_trauma(0.7);
+    assert_eq!(camera.trauma, 1.);
+    camera.update(0.1);
+    let shaken = camera.project(vec2(0., 0.));
+    assert!((shaken - center).length() > 1.);
+    let p = vec2(1.5, -0.5);
+    assert!((camera.unproject(camera.project(p)) - p).length() < 1E-4);
+    for _ in 0..10 {
+        camera.update(0.1);
+    }
+    assert_eq!(camera.trauma, 0.);
+    assert!((camera.project(vec2(0., 0.)) - center).length() < 1E-3);
+}
+
+#[test]
+fn rotated_view() {
+    let mut camera = Camera::new(800., 600.);
+    camera.position2d = vec2(1., 2.);
+    camera.rotation = std::f32::consts::FRAC_PI_2;
+    // screen right is world up after a quarter turn
+    let right = camera.unproject(vec2(600., 300.));
+    assert!((right - vec2(1., 2. + 200. / 800. / INIT_ZOOM)).length() < 1E-4);
+    for p in [vec2(0., 0.), vec2(-3., 1.5), vec2(2., -7.)].iter() {
+        assert!((camera.unproject(camera.project(*p)) - *p).length() < 1E-4);
+    }
+    // zoom and drag still keep the point under the cursor
+    camera.rotation = 0.6;
+    camera.target_position = camera.position2d;
+    let cursor = vec2(120., 500.);
+    let anchor = camera.unproject(cursor);
+    camera.zoom_at(1.5, cursor);
+    camera.snap();
+    assert!((camera.unproject(cursor) - anchor).length() < 1E-4);
+    camera.drag(cursor, vec2(700., 100.));
+    assert!((camera.unproject(vec2(700., 100.)) - anchor).length() < 1E-4);
+}
+
+#[test]
+fn matrices_follow_field_changes() {
+    let mut camera = Camera::new(800., 600.);
+    let before = camera.unproject(vec2(400., 300.));
+    camera.position2d = vec2(3., -1.);
+    let after = camera.unproject(vec2(400., 300.));
+    assert!((after - before - vec2(3., -1.)).length() < 1E-5);
+    camera.update_window(400., 600.);
+    assert!((camera.unproject(vec2(200., 300.)) - vec2(3., -1.)).length() < 1E-5);
+    let projection = camera.get_projection();
+    assert_eq!(projection, camera.get_projection());
+    let product = (projection * camera.matrices().1).to_cols_array();
+    let identity = Mat4::identity().to_cols_array();
+    assert!(product
+        .iter()
+        .zip(identity.iter())
+        .all(|(a, b)| (a - b).abs() < 1E-5));
 }
@@ -0,0 +1,225 @@
+use crate::geometry::{polygon_segments, segment_distance, segments_contain};
+use crate::world::World;
+use glam::{vec2, Vec2};
+
+/// Max number of sphere tracing steps, same as in the shadow shader
+pub const MAX_STEPS: usize = 64;
+
+/// Signed distance to occluders (negative inside) sampled at texel centers of a world rect
+/// Rows are stored like GL textures: the first row is the bottom one
+pub struct DistanceField {
+    pub width: usize,
+    pub height: usize,
+    /// (min, max) corners of the covered world rect
+    pub rect: (Vec2, Vec2),
+    pub data: Vec<f32>,
+}
+
+impl DistanceField {
+    /// Field without occluders
+    pub fn new(width: usize, height: usize, rect: (Vec2, Vec2)) -> DistanceField {
+        let far = (rect.1 - rect.0).length();
+        DistanceField {
+            width,
+            height,
+            rect,
+            data: vec![far; width * height],
+        }
+    }
+
+    /// Bake occluders of the world over its bounds
+    pub fn from_world(world: &World, width: usize, height: usize) -> DistanceField {
+        let mut field = DistanceField::new(width, height, world.bounds);
+        field.bake(world);
+        field
+    }
+
+    /// Recompute distances to the current occluders over the world bounds
+    pub fn bake(&mut self, world: &World) {
+        self.rect = world.bounds;
+        let far = self.max_distance();
+        let occluders: Vec<_> = world
+            .occluders
+            .iter()
+            .map(|occluder| polygon_segments(&occluder.placed_polygon()))
+            .collect();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let p = self.texel_center(x, y);
+                self.data[y * self.width + x] = occluders_distance(&occluders, p, far).0;
+            }
+        }
+    }
+
+    /// Largest distance which can be stored, the diagonal of the rect
+    pub fn max_distance(&self) -> f32 {
+        (self.rect.1 - self.rect.0).length()
+    }
+
+    /// Size of one texel in world units
+    pub fn texel_size(&self) -> Vec2 {
+        (self.rect.1 - self.rect.0) / vec2(self.width as f32, self.height as f32)
+    }
+
+    pub fn texel_center(&self, x: usize, y: usize) -> Vec2 {
+        self.rect.0 + vec2(x as f32 + 0.5, y as f32 + 0.5) * self.texel_size()
+    }
+
+    /// Distance at the nearest texel (the texture is not filtered, bytes of distances can't be mixed)
+    /// Points outside of the rect get the distance of the border texel
+    pub fn sample(&self, point: Vec2) -> f32 {
+        self.data[self.texel_index(point)]
+    }
+
+    /// Index in `data` of the texel nearest to the point, clamped to the rect
+    pub fn texel_index(&self, point: Vec2) -> usize {
+        let texel = (point - self.rect.0) / self.texel_size();
+        let x = (texel.x().max(0.) as usize).min(self.width - 1);
+        let y = (texel.y().max(0.) as usize).min(self.height - 1);
+        y * self.width + x
+    }
+
+    /// Light visibility in [0, 1] of the point by sphere tracing towards the light
+    /// Penumbra grows with softness(light size), mirrors the shadow shader
+    pub fn soft_shadow(&self, point: Vec2, light: Vec2, softness: f32) -> f32 {
+        let to_light = light - point;
+        let length = to_light.length();
+        if length == 0. {
+            return 1.;
+        }
+        self.trace(point, to_light / length, length, softness)
+    }
+
+    /// Same as `soft_shadow` for parallel rays of the light going in the direction
+    pub fn directional_soft_shadow(&self, point: Vec2, direction: Vec2, softness: f32) -> f32 {
+        self.trace(point, -direction.normalize(), self.max_distance(), softness)
+    }
+
+    fn trace(&self, point: Vec2, dir: Vec2, length: f32, softness: f32) -> f32 {
+        if self.sample(point) < 0. {
+            return 0.;
+        }
+        let texel = self.texel_size().x().max(self.texel_size().y());
+        // penumbra width grows as softness * t / length (light disk seen from the point)
+        let k = length / softness.max(1E-3);
+        let mut visibility: f32 = 1.;
+        // start outside of the texel, so lit surfaces don't shadow themselves
+        let mut t = 2. * texel;
+        for _ in 0..MAX_STEPS {
+            if t >= length {
+                break;
+            }
+            let h = self.sample(point + dir * t);
+            if h < 0. {
+                return 0.;
+            }
+            visibility = visibility.min(k * h / t);
+            t += h.max(0.5 * texel);
+        }
+        visibility.clamp(0., 1.)
+    }
+
+    /// Distances packed into 16 bits of red and green channels, [-max_distance, max_distance] range
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        let range = self.max_distance();
+        let mut bytes = Vec::with_capacity(self.data.len() * 4);
+        for d in self.data.iter() {
+            let normalized = (d / range * 0.5 + 0.5).clamp(0., 1.);
+            let value = (normalized * 65535.).round() as u16;
+            bytes.extend_from_slice(&[(value >> 8) as u8, value as u8, 0, 255]);
+        }
+        bytes
+    }
+}
+
+/// Signed distance to the nearest of occluders given by their segments and its index, `far` if there are none
+pub fn occluders_distance(
+    occluders: &[Vec<(Vec2, Vec2)>],
+    point: Vec2,
+    far: f32,
+) -> (f32, Option<usize>) {
+    let mut distance = far;
+    let mut nearest = None;
+    let mut inside = false;
+    for (i, segments) in occluders.iter().enumerate() {
+        for (a, b) in segments.iter() {
+            let d = segment_distance(point, *a, *b);
+            if d < distance {
+                distance = d;
+                nearest = Some(i);
+            }
+        }
+        inside = inside || segments_contain(segments, point);
+    }
+    (if inside { -distance } else { distance }, nearest)
+}
+
+/// Inverse of the packing of `DistanceField::to_rgba8`, as in the shader
+pub fn unpack_distance(bytes: [u8; 2], max_distance: f32) -> f32 {
+    let value = bytes[0] as f32 * 256. + bytes[1] as f32;
+    (value / 65535. - 0.5) * 2. * max_distance
+}
+
+#[cfg(test)]
+fn square_field() -> DistanceField {
+    use crate::world::Occluder;
+    let mut world = World::default();
+    world.bounds = (vec2(-2., -2.), vec2(2., 2.));
+    world.occluders.push(Occluder::from(&crate::scene::OccluderDesc {
+        exterior: vec![[-0.25, -0.25], [0.25, -0.25], [0.25, 0.25], [-0.25, 0.25]],
+        holes: vec![],
+        position: [0., 0.],
+        angle: 0.,
+        scale: [1., 1.],
+        velocity: [0., 0.],
+        angular_velocity: 0.,
+        fixed: true,
+        emission: [0., 0., 0.],
+    }));
+    DistanceField::from_world(&world, 128, 128)
+}
+
+#[test]
+fn baked_distances() {
+    let field = square_field();
+    let texel = field.texel_size().x();
+    assert!((field.sample(vec2(1., 0.)) - 0.75).abs() < texel);
+    assert!((field.sample(vec2(0., 0.)) + 0.25).abs() < texel);
+    assert!(field.sample(vec2(0.2, 0.)) < 0.);
+    // the first row is the bottom one
+    assert!(field.texel_center(0, 0).y() < field.texel_center(0, 127).y());
+}
+
+#[test]
+fn packing_round_trip() {
+    let field = square_field();
+    let bytes = field.to_rgba8();
+    let range = field.max_distance();
+    for (i, d) in field.data.iter().enumerate() {
+        let unpacked = unpack_distance([bytes[4 * i], bytes[4 * i + 1]], range);
+        assert!((unpacked - d).abs() < 2. * range / 65535.);
+    }
+}
+
+#[test]
+fn square_soft_shadow() {
+    let field = square_field();
+    let light = vec2(-1.5, 0.);
+    // right behind the square is umbra, far aside is lit
+    assert_eq!(field.soft_shadow(vec2(1., 0.), light, 0.1), 0.);
+    assert_eq!(field.soft_shadow(vec2(1., 1.5), light, 0.1), 1.);
+    // visibility grows across the penumbra
+    let penumbra: Vec<_> = (0..8)
+        .map(|i| field.soft_shadow(vec2(1.5, 0.4 + 0.2 * i as f32), light, 0.3))
+        .collect();
+    assert!(penumbra.windows(2).all(|w| w[0] <= w[1]));
+    assert_eq!((penumbra[0], penumbra[7]), (0., 1.));
+    assert!(penumbra[2] > 0. && penumbra[2] < 1.);
+    // bigger light gives softer shadow
+    let p = vec2(1.5, 0.8);
+    assert!(field.soft_shadow(p, light, 0.05) > field.soft_shadow(p, light, 0.5));
+    // sun from the left casts the same umbra
+    assert_eq!(field.directional_soft_shadow(vec2(1., 0.), vec2(1., 0.), 0.1), 0.);
+    // inside of the occluder is dark
+    assert_eq!(field.soft_shadow(vec2(0., 0.), light, 0.1), 0.);
+}
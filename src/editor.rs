@@ -0,0 +1,261 @@
+use crate::world::{Occluder, World};
+use geo::{LineString, Polygon};
+use glam::{vec2, Vec2};
+
+/// Distance (world units) at which vertices are grabbed, if not set from the camera
+const DEFAULT_PICK_RADIUS: f32 = 0.05;
+
+/// Vertex of occluder's polygon, ring 0 is the exterior, next ones are holes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VertexId {
+    pub occluder: usize,
+    pub ring: usize,
+    pub index: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Drag {
+    Vertex(VertexId),
+    Polygon { occluder: usize, last: Vec2 },
+}
+
+/// Authoring of occluders, all points are in world coords (unproject the mouse by the camera)
+/// Click places vertices of a new polygon, click on its first vertex finishes it,
+/// dragging moves vertices and whole polygons
+pub struct Editor {
+    /// Vertices of the polygon being placed
+    pub new_polygon: Vec<Vec2>,
+    /// Occluder clicked last
+    pub selected: Option<usize>,
+    pub pick_radius: f32,
+    drag: Option<Drag>,
+}
+
+impl Default for Editor {
+    fn default() -> Editor {
+        Editor {
+            new_polygon: vec![],
+            selected: None,
+            pick_radius: DEFAULT_PICK_RADIUS,
+            drag: None,
+        }
+    }
+}
+
+impl Editor {
+    pub fn mouse_down(&mut self, world: &mut World, point: Vec2) {
+        if self.new_polygon.len() >= 3 && (self.new_polygon[0] - point).length() < self.pick_radius
+        {
+            self.finish_polygon(world);
+            return;
+        }
+        if self.new_polygon.is_empty() {
+            if let Some(vertex) = self.pick_vertex(world, point) {
+                self.selected = Some(vertex.occluder);
+                self.drag = Some(Drag::Vertex(vertex));
+                return;
+            }
+            if let Some(occluder) = world.pick(point) {
+                self.selected = Some(occluder);
+                self.drag = Some(Drag::Polygon {
+                    occluder,
+                    last: point,
+                });
+                return;
+            }
+        }
+        self.selected = None;
+        self.new_polygon.push(point);
+    }
+
+    pub fn mouse_move(&mut self, world: &mut World, point: Vec2) {
+        match self.drag {
+            Some(Drag::Vertex(vertex)) => {
+                move_vertex(&mut world.occluders[vertex.occluder], vertex, point)
+            }
+            Some(Drag::Polygon { occluder, last }) => {
+                world.occluders[occluder].transform.translation += point - last;
+                self.drag = Some(Drag::Polygon {
+                    occluder,
+                    last: point,
+                });
+            }
+            None => {}
+        }
+    }
+
+    pub fn mouse_up(&mut self) {
+        self.drag = None;
+    }
+
+    /// Turn placed vertices into a fixed occluder, returns its index
+    /// Nothing happens if there are less than 3 vertices
+    pub fn finish_polygon(&mut self, world: &mut World) -> Option<usize> {
+        if self.new_polygon.len() < 3 {
+            return None;
+        }
+        let points = std::mem::take(&mut self.new_polygon);
+        let center = points.iter().fold(vec2(0., 0.), |sum, p| sum + *p) / points.len() as f32;
+        let ring: Vec<_> = points
+            .iter()
+            .map(|p| (p.x() - center.x(), p.y() - center.y()))
+            .collect();
+        world
+            .occluders
+            .push(Occluder::fixed(Polygon::new(ring.into(), vec![]), center));
+        self.selected = Some(world.occluders.len() - 1);
+        self.selected
+    }
+
+    /// Remove the last placed vertex, or the selected occluder if no vertices are placed
+    pub fn delete(&mut self, world: &mut World) {
+        if self.new_polygon.pop().is_some() {
+            return;
+        }
+        if let Some(i) = self.selected.take() {
+            if i < world.occluders.len() {
+                world.occluders.remove(i);
+            }
+            self.drag = None;
+        }
+    }
+
+    /// Forget placed vertices and selection, call it when occluders are replaced
+    pub fn reset(&mut self) {
+        self.new_polygon.clear();
+        self.selected = None;
+        self.drag = None;
+    }
+
+    /// The nearest vertex in pick radius
+    pub fn pick_vertex(&self, world: &World, point: Vec2) -> Option<VertexId> {
+        let mut best = None;
+        let mut best_distance = self.pick_radius;
+        for (occluder_id, occluder) in world.occluders.iter().enumerate() {
+            for (ring_id, ring) in rings(&occluder.polygon).iter().enumerate() {
+                for (index, vertex) in ring.iter().enumerate() {
+                    let distance = (occluder.transform.apply(*vertex) - point).length();
+                    if distance < best_distance {
+                        best_distance = distance;
+                        best = Some(VertexId {
+                            occluder: occluder_id,
+                            ring: ring_id,
+                            index,
+                        });
+                    }
+                }
+            }
+        }
+        best
+    }
+}
+
+/// Points of exterior and holes without closing points
+fn rings(polygon: &Polygon<f32>) -> Vec<Vec<Vec2>> {
+    std::iter::once(polygon.exterior())
+        .chain(polygon.interiors().iter())
+        .map(|ring| {
+            let mut points: Vec<_> = ring.points_iter().map(|p| vec2(p.x(), p.y())).collect();
+            if points.len() > 1 && points.first() == points.last() {
+                points.pop();
+            }
+            points
+        })
+        .collect()
+}
+
+fn move_vertex(occluder: &mut Occluder, vertex: VertexId, point: Vec2) {
+    let mut rings = rings(&occluder.polygon);
+    rings[vertex.ring][vertex.index] = occluder.transform.inverse_apply(point);
+    let mut rings = rings.into_iter().map(|ring| {
+        ring.iter()
+            .map(|p| (p.x(), p.y()))
+            .collect::<Vec<_>>()
+            .into()
+    });
+    let exterior: LineString<f32> = rings.next().unwrap();
+    occluder.polygon = Polygon::new(exterior, rings.collect());
+}
+
+#[cfg(test)]
+fn click(editor: &mut Editor, world: &mut World, point: Vec2) {
+    editor.mouse_down(world, point);
+    editor.mouse_up();
+}
+
+#[test]
+fn place_polygon() {
+    let mut world = World::default();
+    let mut editor = Editor::default();
+    for p in [vec2(0., 0.), vec2(1., 0.), vec2(1., 1.)].iter() {
+        click(&mut editor, &mut world, *p);
+    }
+    assert!(world.occluders.is_empty());
+    // click near the first vertex closes the polygon
+    click(&mut editor, &mut world, vec2(0.01, 0.));
+    assert_eq!(world.occluders.len(), 1);
+    assert!(editor.new_polygon.is_empty());
+    assert_eq!(editor.selected, Some(0));
+    assert!(world.occluders[0].fixed);
+    assert!(world.occluders[0].contains(vec2(0.7, 0.3)));
+    assert!(!world.occluders[0].contains(vec2(0.3, 0.7)));
+}
+
+#[test]
+fn finish_needs_three_vertices() {
+    let mut world = World::default();
+    let mut editor = Editor::default();
+    click(&mut editor, &mut world, vec2(0., 0.));
+    click(&mut editor, &mut world, vec2(1., 0.));
+    assert_eq!(editor.finish_polygon(&mut world), None);
+    click(&mut editor, &mut world, vec2(0., 1.));
+    assert_eq!(editor.finish_polygon(&mut world), Some(0));
+}
+
+#[test]
+fn drag_vertex_of_transformed_occluder() {
+    let mut world = World::default();
+    let mut editor = Editor::default();
+    for p in [vec2(0., 0.), vec2(1., 0.), vec2(1., 1.), vec2(0., 1.)].iter() {
+        click(&mut editor, &mut world, *p);
+    }
+    editor.finish_polygon(&mut world);
+    world.occluders[0].transform.rotation = 0.3;
+    world.occluders[0].transform.scale = vec2(2., 0.5);
+    let corner = world.occluders[0].transform.apply(vec2(0.5, 0.5));
+    editor.mouse_down(&mut world, corner + vec2(0.01, 0.));
+    editor.mouse_move(&mut world, vec2(3., 3.));
+    editor.mouse_up();
+    let moved = world.occluders[0].placed_polygon();
+    assert!(moved
+        .exterior()
+        .points_iter()
+        .any(|p| (vec2(p.x(), p.y()) - vec2(3., 3.)).length() < 1E-4));
+    // ring stays closed
+    let ring = &moved.exterior().0;
+    assert_eq!(ring.first(), ring.last());
+}
+
+#[test]
+fn drag_and_delete_polygon() {
+    let mut world = World::default();
+    let mut editor = Editor::default();
+    for p in [vec2(0., 0.), vec2(1., 0.), vec2(0., 1.)].iter() {
+        click(&mut editor, &mut world, *p);
+    }
+    editor.finish_polygon(&mut world);
+    let start = world.occluders[0].transform.translation;
+    editor.mouse_down(&mut world, vec2(0.2, 0.2));
+    editor.mouse_move(&mut world, vec2(1.2, 0.7));
+    editor.mouse_up();
+    let shift = world.occluders[0].transform.translation - start;
+    assert!((shift - vec2(1., 0.5)).length() < 1E-5);
+    // click on empty space starts a new polygon, delete removes its vertex first
+    click(&mut editor, &mut world, vec2(-2., -2.));
+    editor.delete(&mut world);
+    assert!(editor.new_polygon.is_empty());
+    assert_eq!(world.occluders.len(), 1);
+    click(&mut editor, &mut world, vec2(1.3, 0.8));
+    editor.delete(&mut world);
+    assert!(world.occluders.is_empty());
+}
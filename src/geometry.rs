@@ -3,6 +3,8 @@ use geo::{LineString, Polygon};
 use glam::{vec2, vec3, Vec2, Vec3};
 use quad_rand as qrand;
 
+use crate::transform::Transform2D;
+
 const SHADOW_SIZE: f32 = 20f32;
 
 /// Sample points and find it's convex hull
@@ -20,47 +22,162 @@ pub fn generate_convex_polygon(samples_num: usize, size: f32) -> Polygon<f32> {
     polygon.convex_hull()
 }
 
-/// Full graph on vertices of polygon
-pub fn _shadow_segments(polygon: &Polygon<f32>) -> Vec<(Vec2, Vec2)> {
-    let ex: Vec<_> = polygon.exterior().points_iter().collect();
+/// L-shaped wall, concave polygon
+pub fn generate_l_polygon(size: f32, width: f32) -> Polygon<f32> {
+    let points = vec![
+        (-size, -size),
+        (size, -size),
+        (size, -size + width),
+        (-size + width, -size + width),
+        (-size + width, size),
+        (-size, size),
+    ];
+    Polygon::new(LineString::from(points), vec![])
+}
+
+/// Square room with walls of `width` and a pillar inside, polygon with holes
+pub fn generate_room_polygon(size: f32, width: f32) -> Vec<Polygon<f32>> {
+    let square = |size: f32| {
+        LineString::from(vec![
+            (-size, -size),
+            (size, -size),
+            (size, size),
+            (-size, size),
+        ])
+    };
+    let pillar_size = width * 0.5;
+    vec![
+        Polygon::new(square(size), vec![square(size - width)]),
+        Polygon::new(square(pillar_size), vec![]),
+    ]
+}
+
+/// Shift of the rays casted around each vertex in `visibility_polygon`
+const RAY_ANGLE_SHIFT: f32 = 1E-4;
+
+/// All edges (exterior and holes) of polygon
+pub fn polygon_segments(polygon: &Polygon<f32>) -> Vec<(Vec2, Vec2)> {
     let mut res = vec![];
-    for i in 0..ex.len() {
-        for j in 0..ex.len() {
-            if i == j {
-                continue;
-            }
-            let a = ex[i];
-            let b = ex[j];
-            res.push((vec2(a.x(), a.y()), vec2(b.x(), b.y())));
+    for ring in std::iter::once(polygon.exterior()).chain(polygon.interiors().iter()) {
+        // rings in geo are closed -- the last point equals the first one
+        for pair in ring.0.windows(2) {
+            res.push((vec2(pair[0].x, pair[0].y), vec2(pair[1].x, pair[1].y)));
         }
     }
     res
 }
 
-// Polygons are small for this demo.
-//O(n^2)
-/// Find ligh blocking segment for polygon mesh from light source
-pub fn brute_shadow_segment(polygon: &Polygon<f32>, position: Vec2, light: Vec2) -> (Vec2, Vec2) {
-    let ex: Vec<_> = polygon.exterior().points_iter().collect();
-    let mut res = (vec2(0., 0.), vec2(0., 0.));
-    let mut max_angle = 0.;
-    for i in 0..ex.len() {
-        for j in 0..ex.len() {
-            if i == j {
-                continue;
+/// Move polygon from model to world coords
+pub fn translate_polygon(polygon: &Polygon<f32>, position: Vec2) -> Polygon<f32> {
+    let translate = |ring: &LineString<f32>| -> LineString<f32> {
+        ring.points_iter()
+            .map(|p| (p.x() + position.x(), p.y() + position.y()))
+            .collect::<Vec<_>>()
+            .into()
+    };
+    Polygon::new(
+        translate(polygon.exterior()),
+        polygon.interiors().iter().map(translate).collect(),
+    )
+}
+
+/// Point is inside the polygon and not in its holes (even-odd rule)
+pub fn polygon_contains(polygon: &Polygon<f32>, point: Vec2) -> bool {
+    segments_contain(&polygon_segments(polygon), point)
+}
+
+/// Point is inside the closed rings made of the segments (even-odd rule)
+pub fn segments_contain(segments: &[(Vec2, Vec2)], point: Vec2) -> bool {
+    let mut inside = false;
+    for (a, b) in segments.iter() {
+        if (a.y() > point.y()) != (b.y() > point.y()) {
+            let x = a.x() + (point.y() - a.y()) / (b.y() - a.y()) * (b.x() - a.x());
+            if point.x() < x {
+                inside = !inside;
             }
-            let origin_a = vec2(ex[i].x(), ex[i].y());
-            let origin_b = vec2(ex[j].x(), ex[j].y());
-            let a = position + origin_a - light;
-            let b = position + origin_b - light;
-            let cur_angle = shortest_angle(a, b);
-            if cur_angle > max_angle {
-                max_angle = cur_angle;
-                res = (origin_a, origin_b);
+        }
+    }
+    inside
+}
+
+/// Distance from the point to the nearest point of the segment
+pub fn segment_distance(point: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let (pa, ba) = (point - a, b - a);
+    // degenerate segment is its first point
+    let h = if ba.dot(ba) > 0. {
+        (pa.dot(ba) / ba.dot(ba)).clamp(0., 1.)
+    } else {
+        0.
+    };
+    (pa - ba * h).length()
+}
+
+/// Distance along the ray (in `dir` units) to the segment, if the ray hits it
+pub fn ray_segment_intersection(origin: Vec2, dir: Vec2, segment: (Vec2, Vec2)) -> Option<f32> {
+    let edge = segment.1 - segment.0;
+    let divisor = det(dir.x(), edge.x(), dir.y(), edge.y());
+    if divisor.abs() < EPS {
+        return None;
+    }
+    let diff = segment.0 - origin;
+    let t = det(diff.x(), edge.x(), diff.y(), edge.y()) / divisor;
+    let u = det(diff.x(), dir.x(), diff.y(), dir.y()) / divisor;
+    if t >= 0. && (0. ..=1.).contains(&u) {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+/// Area visible from the light.
+/// Angular sweep: rays are casted towards every vertex (and slightly around it to look behind corners),
+/// the nearest hit of each ray becomes the vertex of visibility polygon.
+/// occluders -- world coords
+/// bounds -- (min, max) corners of the world, light should be inside
+pub fn visibility_polygon(
+    light: Vec2,
+    occluders: &[Polygon<f32>],
+    bounds: (Vec2, Vec2),
+) -> Polygon<f32> {
+    let (min, max) = bounds;
+    let corners = [min, vec2(max.x(), min.y()), max, vec2(min.x(), max.y())];
+    let mut segments: Vec<_> = (0..4).map(|i| (corners[i], corners[(i + 1) % 4])).collect();
+    for polygon in occluders {
+        segments.extend(polygon_segments(polygon));
+    }
+
+    let mut angles = vec![];
+    for (a, _) in segments.iter() {
+        let angle = polar_angle(*a - light);
+        angles.push(angle - RAY_ANGLE_SHIFT);
+        angles.push(angle);
+        angles.push(angle + RAY_ANGLE_SHIFT);
+    }
+    angles.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let mut points: Vec<Vec2> = vec![];
+    for angle in angles {
+        let dir = vec2(angle.cos(), angle.sin());
+        let nearest = segments
+            .iter()
+            .filter_map(|s| ray_segment_intersection(light, dir, *s))
+            .fold(None, |acc: Option<f32>, t| {
+                Some(acc.map_or(t, |m| m.min(t)))
+            });
+        if let Some(t) = nearest {
+            let point = light + dir * t;
+            // rays close to each other often hit the same point
+            let repeated = match points.last() {
+                Some(last) => (*last - point).length() <= 1E-5,
+                None => false,
+            };
+            if !repeated {
+                points.push(point);
             }
         }
     }
-    res
+    let points: Vec<(f32, f32)> = points.iter().map(|p| (p.x(), p.y())).collect();
+    Polygon::new(LineString::from(points), vec![])
 }
 
 /// Angle from positive x axis
@@ -73,28 +190,335 @@ fn polar_angle(vec: Vec2) -> f32 {
     }
 }
 
-/// Shortest angle between two radius vectors
-fn shortest_angle(a: Vec2, b: Vec2) -> f32 {
-    let a = (polar_angle(a) - polar_angle(b)).abs();
-    if a > std::f32::consts::PI {
-        2. * std::f32::consts::PI - a
+/// Vertex of shadow volume triangle list
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowPoint {
+    pub pos: Vec2,
+    /// Projective coordinates of shadow darkness, x / z goes from 0 (lit) to 1 (umbra)
+    pub normal: Vec3,
+}
+
+/// Homogeneous coordinate of penumbra wedge apex, should be small but not zero to avoid 0 / 0
+const PENUMBRA_APEX_Z: f32 = 1E-4;
+
+/// Twice the signed area of the ring, positive for counter clockwise rings
+fn ring_orientation(ring: &[Vec2]) -> f32 {
+    (0..ring.len())
+        .map(|i| {
+            let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+            det(a.x(), a.y(), b.x(), b.y())
+        })
+        .sum()
+}
+
+/// Rings of polygon (exterior and holes), solid part of the polygon is on the left of each edge
+fn solid_rings(polygon: &Polygon<f32>) -> Vec<Vec<Vec2>> {
+    std::iter::once(oriented_ring(polygon.exterior(), true))
+        .chain(
+            polygon
+                .interiors()
+                .iter()
+                .map(|ring| oriented_ring(ring, false)),
+        )
+        .filter(|ring| ring.len() > 2)
+        .collect()
+}
+
+/// Edge with solid on the left is facing away from the light
+fn is_back_facing(a: Vec2, b: Vec2, light: Vec2) -> bool {
+    is_facing_along(a, b, a - light)
+}
+
+/// Outward normal of edge (solid on the left) looks along the direction
+fn is_facing_along(a: Vec2, b: Vec2, direction: Vec2) -> bool {
+    let edge = b - a;
+    let outward = vec2(edge.y(), -edge.x());
+    outward.dot(direction) > 0.
+}
+
+/// Edges of polygon (world coords) which are facing away from the light
+/// Every edge is oriented so that solid part of the polygon is on the left
+pub fn back_facing_edges(polygon: &Polygon<f32>, light: Vec2) -> Vec<(Vec2, Vec2)> {
+    let mut res = vec![];
+    for ring in solid_rings(polygon) {
+        for i in 0..ring.len() {
+            let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+            if is_back_facing(a, b, light) {
+                res.push((a, b));
+            }
+        }
+    }
+    res
+}
+
+/// (outer, inner) directions of penumbra wedge at silhouette vertex
+/// Rays go from opposite edges of the light disk through the vertex
+/// inside -- any point on the shadow side of the ray from light center through vertex
+pub fn penumbra_rays(vertex: Vec2, inside: Vec2, light: Vec2, light_radius: f32) -> (Vec2, Vec2) {
+    let dir = (vertex - light).normalize();
+    let mut normal = vec2(-dir.y(), dir.x());
+    if normal.dot(inside - vertex) < 0. {
+        normal = -normal;
+    }
+    let outer = (vertex - (light + normal * light_radius)).normalize();
+    let inner = (vertex - (light - normal * light_radius)).normalize();
+    (outer, inner)
+}
+
+/// Construct soft shadow of polygon from the light disk with radius `light_radius`
+/// Umbra is extruded from every back facing edge, penumbra wedges are added at silhouette vertices,
+/// so penumbra width depends on light radius and distance to the occluder.
+/// transform -- places polygon in the world
+/// Returns triangle list (3 points per triangle) in world coords
+pub fn shadow_volume(
+    polygon: &Polygon<f32>,
+    transform: &Transform2D,
+    light: Vec2,
+    light_radius: f32,
+) -> Vec<ShadowPoint> {
+    let rings = solid_rings(&transform.apply_polygon(polygon));
+    if rings.is_empty() {
+        return vec![];
+    }
+    let umbra = vec3(1., 0., 1.);
+    let mut res = vec![];
+    for ring in rings.iter() {
+        let n = ring.len();
+        let back: Vec<_> = (0..n)
+            .map(|i| is_back_facing(ring[i], ring[(i + 1) % n], light))
+            .collect();
+        let mut dirs: Vec<_> = ring.iter().map(|p| (*p - light).normalize()).collect();
+        let mut silhouette = vec![];
+        // penumbra wedges
+        for i in 0..n {
+            let prev = (i + n - 1) % n;
+            // neighbour vertex is inside the shadow
+            let neighbour = match (back[prev], back[i]) {
+                (false, true) => (i + 1) % n,
+                (true, false) => prev,
+                _ => continue,
+            };
+            let (outer, inner) = penumbra_rays(ring[i], ring[neighbour], light, light_radius);
+            // umbra is extruded along the inner ray of silhouette vertex
+            dirs[i] = inner;
+            silhouette.push(i);
+            res.push(ShadowPoint {
+                pos: ring[i],
+                normal: vec3(0., 0., PENUMBRA_APEX_Z),
+            });
+            res.push(ShadowPoint {
+                pos: ring[i] + outer * SHADOW_SIZE,
+                normal: vec3(0., 0., 1.),
+            });
+            res.push(ShadowPoint {
+                pos: ring[i] + inner * SHADOW_SIZE,
+                normal: umbra,
+            });
+        }
+        // inner rays cross behind the occluder when the light is wider than it,
+        // the umbra ends there and only penumbra wedges go further
+        let apex = match silhouette[..] {
+            [a, b] => rays_crossing((ring[a], dirs[a]), (ring[b], dirs[b]))
+                .filter(|p| (*p - ring[a]).length() < SHADOW_SIZE),
+            _ => None,
+        };
+        let far = |i: usize| apex.unwrap_or(ring[i] + dirs[i] * SHADOW_SIZE);
+        // umbra
+        for i in (0..n).filter(|i| back[*i]) {
+            let j = (i + 1) % n;
+            let quad = [far(i), ring[i], ring[j], far(j)];
+            for k in [0, 1, 2, 3, 2, 0].iter() {
+                res.push(ShadowPoint {
+                    pos: quad[*k],
+                    normal: umbra,
+                });
+            }
+        }
+    }
+    res
+}
+
+/// Point where two rays (origin, direction) cross, None for parallel or diverging rays
+fn rays_crossing(a: (Vec2, Vec2), b: (Vec2, Vec2)) -> Option<Vec2> {
+    let divisor = det(a.1.x(), b.1.x(), a.1.y(), b.1.y());
+    if divisor.abs() < EPS {
+        return None;
+    }
+    let diff = b.0 - a.0;
+    let t = det(diff.x(), b.1.x(), diff.y(), b.1.y()) / divisor;
+    let s = det(diff.x(), a.1.x(), diff.y(), a.1.y()) / divisor;
+    if t > 0. && s > 0. {
+        Some(a.0 + a.1 * t)
     } else {
-        a
+        None
     }
 }
 
-/// Construct shadow quadrangle from ligh blocking segment and light position
-/// position -- is the position of segment in the world
-/// segment -- model coords
-pub fn shadow_shape(segment: (Vec2, Vec2), light: Vec2, position: Vec2) -> [Vec2; 4] {
-    let dir0 = (segment.0 + position - light).normalize();
-    let dir1 = (segment.1 + position - light).normalize();
-    [
-        position + segment.0 + dir0 * SHADOW_SIZE,
-        position + segment.0,
-        position + segment.1,
-        position + segment.1 + dir1 * SHADOW_SIZE,
-    ]
+/// Light direction which can be normalized, zero direction points down instead
+pub fn nonzero_direction(direction: Vec2) -> Vec2 {
+    if direction.length() > EPS {
+        direction
+    } else {
+        vec2(0., -1.)
+    }
+}
+
+/// Construct shadow of polygon from directional light (sun), all rays are parallel
+/// Every back facing edge is extruded along the light direction, there is no penumbra
+/// transform -- places polygon in the world
+/// Returns triangle list (3 points per triangle) in world coords
+pub fn directional_shadow_volume(
+    polygon: &Polygon<f32>,
+    transform: &Transform2D,
+    direction: Vec2,
+) -> Vec<ShadowPoint> {
+    let direction = nonzero_direction(direction);
+    let extrusion = direction.normalize() * SHADOW_SIZE;
+    let mut res = vec![];
+    for ring in solid_rings(&transform.apply_polygon(polygon)) {
+        for i in 0..ring.len() {
+            let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+            if !is_facing_along(a, b, direction) {
+                continue;
+            }
+            let quad = [a + extrusion, a, b, b + extrusion];
+            for k in [0, 1, 2, 3, 2, 0].iter() {
+                res.push(ShadowPoint {
+                    pos: quad[*k],
+                    normal: vec3(1., 0., 1.),
+                });
+            }
+        }
+    }
+    res
+}
+
+/// Points of the ring without closing point, counter clockwise if `ccw` or clockwise otherwise
+fn oriented_ring(ring: &LineString<f32>, ccw: bool) -> Vec<Vec2> {
+    let mut points: Vec<_> = ring
+        .0
+        .iter()
+        .filter(|c| c.x.is_finite() && c.y.is_finite())
+        .map(|c| vec2(c.x, c.y))
+        .collect();
+    if points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    if (ring_orientation(&points) > 0.) != ccw {
+        points.reverse();
+    }
+    points
+}
+
+/// z component of cross product (b - a) x (c - a)
+fn cross(a: Vec2, b: Vec2, c: Vec2) -> f32 {
+    det(b.x() - a.x(), b.y() - a.y(), c.x() - a.x(), c.y() - a.y())
+}
+
+/// Segments intersect not in their endpoints
+fn segments_cross(p: (Vec2, Vec2), q: (Vec2, Vec2)) -> bool {
+    let d1 = cross(q.0, q.1, p.0);
+    let d2 = cross(q.0, q.1, p.1);
+    let d3 = cross(p.0, p.1, q.0);
+    let d4 = cross(p.0, p.1, q.1);
+    d1 * d2 < 0. && d3 * d4 < 0.
+}
+
+/// Connect holes with exterior by bridges, so polygon becomes a single (weakly simple) ring
+/// Result is counter clockwise
+fn merge_holes(polygon: &Polygon<f32>) -> Vec<Vec2> {
+    let mut ring = oriented_ring(polygon.exterior(), true);
+    if ring.len() < 3 {
+        return vec![];
+    }
+    let mut holes: Vec<_> = polygon
+        .interiors()
+        .iter()
+        .map(|hole| oriented_ring(hole, false))
+        .filter(|hole| hole.len() > 2)
+        .collect();
+    // rightmost holes first, then bridges don't cross holes which are not merged yet
+    let max_x = |hole: &Vec<Vec2>| hole.iter().fold(f32::MIN, |acc, p| acc.max(p.x()));
+    holes.sort_by(|a, b| max_x(b).partial_cmp(&max_x(a)).unwrap());
+    for (hole_id, hole) in holes.iter().enumerate() {
+        let (start, m) = hole.iter().enumerate().fold((0, hole[0]), |acc, (i, p)| {
+            if p.x() > acc.1.x() {
+                (i, *p)
+            } else {
+                acc
+            }
+        });
+        let mut edges: Vec<_> = (0..ring.len())
+            .map(|i| (ring[i], ring[(i + 1) % ring.len()]))
+            .collect();
+        for other in holes.iter().skip(hole_id) {
+            edges.extend((0..other.len()).map(|i| (other[i], other[(i + 1) % other.len()])));
+        }
+        let bridge = (0..ring.len())
+            .filter(|i| edges.iter().all(|e| !segments_cross((m, ring[*i]), *e)))
+            .min_by(|a, b| {
+                let da = (ring[*a] - m).length();
+                let db = (ring[*b] - m).length();
+                da.partial_cmp(&db).unwrap()
+            })
+            .unwrap_or(0);
+        let mut merged = ring[..=bridge].to_vec();
+        merged.extend(hole[start..].iter());
+        merged.extend(hole[..=start].iter());
+        merged.extend(ring[bridge..].iter());
+        ring = merged;
+    }
+    ring
+}
+
+/// Ear clipping triangulation of simple polygon with holes
+/// Returns vertices and triangle list indices
+pub fn triangulate(polygon: &Polygon<f32>) -> (Vec<Vec2>, Vec<u16>) {
+    let vertices = merge_holes(polygon);
+    let mut indices = vec![];
+    let mut remaining: Vec<usize> = (0..vertices.len()).collect();
+    while remaining.len() > 2 {
+        let n = remaining.len();
+        let corner = |i: usize| {
+            (
+                vertices[remaining[(i + n - 1) % n]],
+                vertices[remaining[i]],
+                vertices[remaining[(i + 1) % n]],
+            )
+        };
+        // collinear vertices add no area, but no ear could be clipped at them
+        if let Some(i) = (0..n).find(|i| {
+            let (a, b, c) = corner(*i);
+            cross(a, b, c).abs() <= EPS
+        }) {
+            remaining.remove(i);
+            continue;
+        }
+        let is_ear = |i: usize| {
+            let (a, b, c) = corner(i);
+            if cross(a, b, c) <= EPS {
+                return false;
+            }
+            remaining.iter().map(|j| vertices[*j]).all(|p| {
+                p == a
+                    || p == b
+                    || p == c
+                    || cross(a, b, p) <= 0.
+                    || cross(b, c, p) <= 0.
+                    || cross(c, a, p) <= 0.
+            })
+        };
+        // self intersecting polygon has no ears at some point, keep triangulated part
+        let ear = match (0..n).find(|i| is_ear(*i)) {
+            Some(ear) => ear,
+            None => break,
+        };
+        indices.push(remaining[(ear + n - 1) % n] as u16);
+        indices.push(remaining[ear] as u16);
+        indices.push(remaining[(ear + 1) % n] as u16);
+        remaining.remove(ear);
+    }
+    (vertices, indices)
 }
 
 /// Multiply uv on homogeneous coordinate to achive smooth texture interpolation
@@ -110,7 +534,8 @@ pub fn projective_textures(shape: &[Vec2; 4], uv: &[Vec2; 4]) -> [Vec3; 4] {
         }
         let mut uvq = [Default::default(); 4];
         for i in 0..4 {
-            let adj = (i + 2) % 3;
+            // opposite corner of the quad
+            let adj = (i + 2) % 4;
             let homogeneous = if distances[adj] > 0. {
                 (distances[i] + distances[adj]) / distances[adj]
             } else {
@@ -175,6 +600,18 @@ pub fn intersect(line1: MyLine, line2: MyLine) -> Option<Vec2> {
     Some(res)
 }
 
+/// Exterior's indices of polygon vertices
+/// [segment1.point1id, segment1.point2id,
+///  segment2.point1id, segment2.point2id, ...]
+pub fn gen_line_indices_closed(length: u16) -> Vec<u16> {
+    let mut indices = vec![];
+    for i in 0..length {
+        indices.push(i);
+        indices.push((i + 1) % length)
+    }
+    indices
+}
+
 #[test]
 fn check_convex() {
     assert!(generate_convex_polygon(10, 1.).is_convex());
@@ -193,11 +630,317 @@ fn projective_textures_identity() {
     assert_eq!(shader_pass, uv);
 }
 
+#[test]
+fn projective_textures_trapezoid() {
+    // diagonals cross at (0, 2/3), bottom corners are twice as far from it as top ones
+    let shape = [vec2(-2., 0.), vec2(2., 0.), vec2(1., 1.), vec2(-1., 1.)];
+    let uv = [vec2(0., 1.), vec2(1., 1.), vec2(1., 0.), vec2(0., 0.)];
+    let uvq = projective_textures(&shape, &uv);
+    let q: Vec<_> = uvq.iter().map(|v| v.z()).collect();
+    for (q, expected) in q.iter().zip(&[3., 3., 1.5, 1.5]) {
+        assert!((q - expected).abs() < 1E-5);
+    }
+    for i in 0..4 {
+        assert!((uvq[i].x() / uvq[i].z() - uv[i].x()).abs() < 1E-5);
+        assert!((uvq[i].y() / uvq[i].z() - uv[i].y()).abs() < 1E-5);
+    }
+}
+
 #[test]
 fn angle90() {
     assert!((polar_angle(vec2(0., 1.)) - std::f32::consts::PI / 2.).abs() < EPS)
 }
 
+#[test]
+fn visibility_empty_scene() {
+    use geo::algorithm::contains::Contains;
+    let bounds = (vec2(-1., -1.), vec2(1., 1.));
+    let visible = visibility_polygon(vec2(0.2, -0.3), &[], bounds);
+    for p in &[(0.9, 0.9), (-0.9, 0.9), (-0.9, -0.9), (0.9, -0.9), (0., 0.)] {
+        assert!(visible.contains(&geo::Point::new(p.0, p.1)));
+    }
+}
+
+#[test]
+fn visibility_square_occluder() {
+    use geo::algorithm::contains::Contains;
+    let square = Polygon::new(
+        LineString::from(vec![(1., -0.5), (2., -0.5), (2., 0.5), (1., 0.5)]),
+        vec![],
+    );
+    let bounds = (vec2(-5., -5.), vec2(5., 5.));
+    let visible = visibility_polygon(vec2(0., 0.), &[square], bounds);
+    let visible_at = |x: f32, y: f32| visible.contains(&geo::Point::new(x, y));
+    assert!(visible_at(0.5, 0.));
+    assert!(visible_at(0., 3.));
+    assert!(visible_at(-4., -4.));
+    assert!(visible_at(4., 3.));
+    // inside occluder and behind it
+    assert!(!visible_at(1.5, 0.));
+    assert!(!visible_at(3., 0.));
+    assert!(!visible_at(4.5, 1.));
+}
+
+#[test]
+fn visibility_room_with_pillar() {
+    use geo::algorithm::contains::Contains;
+    // light inside the hole of the polygon sees only the hole
+    let room = Polygon::new(
+        LineString::from(vec![(-3., -3.), (3., -3.), (3., 3.), (-3., 3.)]),
+        vec![LineString::from(vec![
+            (-2., -2.),
+            (-2., 2.),
+            (2., 2.),
+            (2., -2.),
+        ])],
+    );
+    let pillar = Polygon::new(
+        LineString::from(vec![(0.5, -0.5), (1., -0.5), (1., 0.5), (0.5, 0.5)]),
+        vec![],
+    );
+    let bounds = (vec2(-5., -5.), vec2(5., 5.));
+    let visible = visibility_polygon(vec2(0., 0.), &[room, pillar], bounds);
+    let visible_at = |x: f32, y: f32| visible.contains(&geo::Point::new(x, y));
+    assert!(visible_at(-1.9, 1.9));
+    assert!(visible_at(0.3, 0.));
+    assert!(!visible_at(1.5, 0.));
+    assert!(!visible_at(2.5, 2.5));
+    assert!(!visible_at(-4., 0.));
+}
+
+#[test]
+fn square_back_faces() {
+    // clockwise square, light on the left
+    let square = Polygon::new(
+        LineString::from(vec![(0., 0.), (0., 1.), (1., 1.), (1., 0.)]),
+        vec![],
+    );
+    let edges = back_facing_edges(&square, vec2(-1., 0.5));
+    assert_eq!(
+        edges,
+        vec![
+            (vec2(1., 0.), vec2(1., 1.)),
+            (vec2(1., 1.), vec2(0., 1.)),
+            (vec2(0., 0.), vec2(1., 0.)),
+        ]
+    );
+}
+
+#[test]
+fn shadow_volume_triangles() {
+    let square = Polygon::new(
+        LineString::from(vec![(0., 0.), (1., 0.), (1., 1.), (0., 1.)]),
+        vec![],
+    );
+    let shadow = shadow_volume(
+        &square,
+        &Transform2D::from_translation(vec2(1., 0.)),
+        vec2(-1., 0.5),
+        0.1,
+    );
+    // 3 umbra quads and 2 penumbra wedges
+    assert_eq!(shadow.len(), 3 * 6 + 2 * 3);
+    // shadow goes away from the light
+    assert!(shadow.iter().all(|p| p.pos.x() >= 1.));
+    for p in shadow.iter() {
+        let x = p.normal.x() / p.normal.z();
+        assert!((0. ..=1.).contains(&x));
+    }
+}
+
+#[test]
+fn directional_shadow_is_parallel() {
+    let square = Polygon::new(
+        LineString::from(vec![(0., 0.), (1., 0.), (1., 1.), (0., 1.)]),
+        vec![],
+    );
+    let shadow = directional_shadow_volume(&square, &Transform2D::default(), vec2(0., -2.));
+    // only the bottom edge faces along the light
+    assert_eq!(shadow.len(), 6);
+    for p in shadow.iter() {
+        assert!(p.pos.x().abs() < EPS || (p.pos.x() - 1.).abs() < EPS);
+        assert!(p.pos.y() <= 0.);
+    }
+}
+
+#[test]
+fn penumbra_width() {
+    let angle = |vertex: Vec2, radius: f32| {
+        let (outer, inner) = penumbra_rays(vertex, vec2(1., 0.), vec2(0., 0.), radius);
+        outer.dot(inner).acos()
+    };
+    assert!(angle(vec2(1., 1.), 0.) < 1E-3);
+    // wider for bigger lights and closer occluders
+    assert!(angle(vec2(1., 1.), 0.2) > angle(vec2(1., 1.), 0.1));
+    assert!(angle(vec2(1., 1.), 0.1) > angle(vec2(2., 2.), 0.1));
+    // outer ray is outside of the shadow
+    let (outer, inner) = penumbra_rays(vec2(1., 1.), vec2(1., 0.), vec2(0., 0.), 0.1);
+    assert!(outer.y() > outer.x());
+    assert!(inner.y() < inner.x());
+}
+
+#[test]
+fn zero_direction_shadow() {
+    let square = Polygon::new(
+        LineString::from(vec![(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)]),
+        vec![],
+    );
+    let shadow = directional_shadow_volume(&square, &Transform2D::default(), vec2(0., 0.));
+    let down = directional_shadow_volume(&square, &Transform2D::default(), vec2(0., -1.));
+    assert!(!shadow.is_empty());
+    assert_eq!(shadow, down);
+}
+
+#[test]
+fn small_occluder_penumbra() {
+    // occluder is much smaller than the light
+    let square = Polygon::new(
+        LineString::from(vec![
+            (-0.05, -0.05),
+            (0.05, -0.05),
+            (0.05, 0.05),
+            (-0.05, 0.05),
+        ]),
+        vec![],
+    );
+    let light = vec2(0., 0.);
+    for radius in [0.25, 0.5].iter() {
+        let shadow = shadow_volume(
+            &square,
+            &Transform2D::from_translation(vec2(1., 0.)),
+            light,
+            *radius,
+        );
+        let (wedges, umbra): (Vec<_>, Vec<_>) = shadow
+            .chunks(3)
+            .partition(|t| t[0].normal.z() == PENUMBRA_APEX_Z);
+        assert_eq!(wedges.len(), 2);
+        // penumbra angle is given by the light radius only
+        for t in wedges.iter() {
+            let (outer, inner) = (t[1].pos - t[0].pos, t[2].pos - t[0].pos);
+            let angle = outer.normalize().dot(inner.normalize()).acos();
+            let expected = 2. * (radius / (t[0].pos - light).length()).atan();
+            assert!((angle - expected).abs() < 1E-3);
+        }
+        // umbra ends right behind the occluder where the inner rays cross
+        assert!(umbra.iter().flat_map(|t| t.iter()).all(|p| p.pos.x() < 1.2));
+    }
+}
+
+/// Sum of triangles areas, checks that all of them are counter clockwise
+#[cfg(test)]
+fn triangulation_area(vertices: &[Vec2], indices: &[u16]) -> f32 {
+    indices
+        .chunks(3)
+        .map(|t| {
+            let area = cross(
+                vertices[t[0] as usize],
+                vertices[t[1] as usize],
+                vertices[t[2] as usize],
+            ) / 2.;
+            assert!(area >= 0.);
+            area
+        })
+        .sum()
+}
+
+#[test]
+fn triangulate_l_shape() {
+    let (vertices, indices) = triangulate(&generate_l_polygon(1., 0.5));
+    assert_eq!(indices.len(), 4 * 3);
+    assert!((triangulation_area(&vertices, &indices) - 1.75).abs() < 1E-5);
+}
+
+#[test]
+fn triangulate_room() {
+    let room = generate_room_polygon(2., 0.5).remove(0);
+    let (vertices, indices) = triangulate(&room);
+    assert!((triangulation_area(&vertices, &indices) - (16. - 9.)).abs() < 1E-4);
+}
+
+#[test]
+fn triangulate_two_holes() {
+    let hole = |x: f32| LineString::from(vec![(x, -1.), (x + 1., -1.), (x + 1., 1.), (x, 1.)]);
+    let polygon = Polygon::new(
+        LineString::from(vec![(-4., -2.), (4., -2.), (4., 2.), (-4., 2.)]),
+        vec![hole(-3.), hole(1.)],
+    );
+    let (vertices, indices) = triangulate(&polygon);
+    assert!((triangulation_area(&vertices, &indices) - (32. - 4.)).abs() < 1E-4);
+}
+
+#[test]
+fn triangulate_empty() {
+    let (_, indices) = triangulate(&Polygon::new(LineString(vec![]), vec![]));
+    assert!(indices.is_empty());
+}
+
+#[test]
+fn triangulate_empty_exterior_with_hole() {
+    let hole = LineString::from(vec![(0., 0.), (1., 0.), (1., 1.)]);
+    let (_, indices) = triangulate(&Polygon::new(LineString(vec![]), vec![hole]));
+    assert!(indices.is_empty());
+}
+
+#[test]
+fn triangulate_not_finite() {
+    let polygon = Polygon::new(
+        LineString::from(vec![(0., 0.), (2., 0.), (f32::NAN, 1.), (2., 2.), (0., 2.)]),
+        vec![LineString::from(vec![
+            (0.5, 0.5),
+            (1., f32::INFINITY),
+            (1.5, 0.5),
+            (1.5, 1.5),
+            (0.5, 1.5),
+        ])],
+    );
+    let (vertices, indices) = triangulate(&polygon);
+    assert!((triangulation_area(&vertices, &indices) - (4. - 1.)).abs() < 1E-4);
+}
+
+#[test]
+fn triangulate_collinear() {
+    let polygon = Polygon::new(
+        LineString::from(vec![
+            (0., 0.),
+            (1., 0.),
+            (2., 0.),
+            (2., 2.),
+            (1., 2.),
+            (0., 2.),
+            (0., 1.),
+        ]),
+        vec![],
+    );
+    let (vertices, indices) = triangulate(&polygon);
+    assert_eq!(indices.len(), 2 * 3);
+    assert!((triangulation_area(&vertices, &indices) - 4.).abs() < 1E-5);
+}
+
+#[test]
+fn triangulate_self_intersecting() {
+    // bow tie, triangles are all counter clockwise and don't cover more than it
+    let polygon = Polygon::new(
+        LineString::from(vec![(0., 0.), (2., 2.), (2., 0.), (0., 2.)]),
+        vec![],
+    );
+    let (vertices, indices) = triangulate(&polygon);
+    assert!(triangulation_area(&vertices, &indices) <= 2. + 1E-5);
+}
+
+#[test]
+fn ray_hits_segment() {
+    let segment = (vec2(1., -1.), vec2(1., 1.));
+    assert_eq!(
+        Some(1.),
+        ray_segment_intersection(vec2(0., 0.), vec2(1., 0.), segment)
+    );
+    assert_eq!(
+        None,
+        ray_segment_intersection(vec2(0., 0.), vec2(-1., 0.), segment)
+    );
+}
+
 #[test]
 fn lines_intersection() {
     let horizontal = MyLine::from_segment(vec2(0., 0.), vec2(1., 0.));
@@ -218,3 +961,37 @@ fn lines_parallel() {
     let horizontal2 = MyLine::from_segment(vec2(0., 1.), vec2(1., 1.));
     assert_eq!(None, intersect(horizontal1, horizontal2));
 }
+
+#[test]
+fn line_indices() {
+    assert_eq!(&gen_line_indices_closed(3), &[0u16, 1, 1, 2, 2, 0]);
+}
+
+#[test]
+fn empty_line_indices() {
+    assert!(gen_line_indices_closed(0).is_empty());
+}
+
+#[test]
+fn contains_respects_holes() {
+    let room = &generate_room_polygon(1., 0.2)[0];
+    assert!(polygon_contains(room, vec2(0.9, 0.)));
+    assert!(!polygon_contains(room, vec2(0.5, 0.)));
+    assert!(!polygon_contains(room, vec2(1.5, 0.)));
+}
+
+#[test]
+fn mirrored_shadow_volume() {
+    // mirrored polygon has reversed orientation, shadow must be the same
+    let polygon = generate_l_polygon(0.3, 0.1);
+    let transform = Transform2D::new(vec2(0.5, 0.), 0.3, vec2(-1., 1.));
+    let mirrored = transform.apply_polygon(&polygon);
+    let light = vec2(-1., 0.2);
+    let expected = shadow_volume(&mirrored, &Transform2D::default(), light, 0.1);
+    let shadow = shadow_volume(&polygon, &transform, light, 0.1);
+    assert!(!shadow.is_empty());
+    assert_eq!(shadow.len(), expected.len());
+    for (a, b) in shadow.iter().zip(expected.iter()) {
+        assert!((a.pos - b.pos).length() < 1E-5);
+    }
+}
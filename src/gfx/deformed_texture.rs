@@ -1,66 +1,32 @@
-use crate::geometry::projective_textures;
-use glam::{vec2, Mat4, Vec2, Vec3};
+use super::stream_buffers::StreamBuffers;
+use crate::sprite::{batch_sprites, Batch, Image, Sprite, TextureId};
+use glam::Mat4;
 use miniquad::*;
-use png;
 
-const MAX_TEXTURE_VERTICES_BYTES: usize = 1000;
-
-#[repr(C)]
-struct TexVertex {
-    pos: Vec2,
-    uv: Vec3,
+/// Image of sprites with its normal map
+struct SpriteTexture {
+    texture: Texture,
+    /// Tangent space normals with height in alpha, sprites without it are lit as the ground
+    normal_map: Option<Texture>,
 }
 
+/// Deformed textured quads (`Sprite`) sorted by texture, one draw call per texture
 pub struct TextureRenderer {
     display_pipeline: Pipeline,
-    display_bindings: Bindings,
-    vertices: Vec<TexVertex>,
-    vertex_buffer: Buffer,
-    pub time: f32,
+    /// Normals of sprites into normal buffer of `ShadowRenderer`
+    normal_pipeline: Pipeline,
+    textures: Vec<SpriteTexture>,
+    /// One buffer per chunk of sprites, indices are u16
+    buffers: Vec<StreamBuffers>,
+    /// Batches of every chunk
+    batches: Vec<Vec<Batch>>,
+    /// Drawn after `update`, textures are ids returned by `add_image`
+    pub sprites: Vec<Sprite>,
 }
 
 impl TextureRenderer {
-    /// Update rendering pipeline with new shadows
-    pub fn deform_texture(&mut self, ctx: &mut Context) {
-        self.vertices.clear();
-        let size = 1.;
-        self.vertices.shrink_to_fit();
-        let vpos = [
-            vec2(-size, -size),
-            vec2(size, -size),
-            vec2(size, f32::sin(self.time) * size),
-            vec2(-1.5 * size, size),
-        ];
-        let uv = [vec2(1., 1.), vec2(0., 1.), vec2(0., 0.), vec2(1., 0.)];
-        let uv = projective_textures(&vpos, &uv);
-        let mut vertices = vec![];
-        for (v, tex) in vpos.iter().zip(uv.iter()) {
-            vertices.push(TexVertex { pos: *v, uv: *tex });
-        }
-        self.vertices = vertices;
-        // vertex_buffer
-        self.vertex_buffer.update(ctx, &self.vertices);
-    }
-
+    /// Renderer without images, add them with `add_image` or `load_png`
     pub fn new(ctx: &mut Context) -> TextureRenderer {
-        let display_vertex_buffer =
-            Buffer::stream(ctx, BufferType::VertexBuffer, MAX_TEXTURE_VERTICES_BYTES);
-
-        let tiger = include_bytes!("../../vintage-robot.png");
-        let decoder = png::Decoder::new(&tiger[..]);
-        let (info, mut reader) = decoder.read_info().unwrap();
-        let mut img_data = vec![0; info.buffer_size()];
-        reader.next_frame(&mut img_data).unwrap();
-        let texture = Texture::from_rgba8(ctx, info.width as u16, info.height as u16, &img_data);
-
-        #[rustfmt::skip]
-        let indices: &[u16] = &[0, 1, 2, 3, 2, 0];
-        let display_index_buffer = Buffer::immutable(ctx, BufferType::IndexBuffer, &indices);
-        let display_bindings = Bindings {
-            vertex_buffers: vec![display_vertex_buffer],
-            index_buffer: display_index_buffer,
-            images: vec![texture],
-        };
         let default_shader = Shader::new(
             ctx,
             display_shader::VERTEX,
@@ -68,14 +34,40 @@ impl TextureRenderer {
             display_shader::META,
         )
         .unwrap();
+        let attributes = [
+            VertexAttribute::new("pos", VertexFormat::Float2),
+            VertexAttribute::new("uv0", VertexFormat::Float3),
+            VertexAttribute::new("tangent", VertexFormat::Float2),
+            VertexAttribute::new("bitangent", VertexFormat::Float2),
+            VertexAttribute::new("tint", VertexFormat::Float4),
+        ];
         let display_pipeline = Pipeline::with_params(
             ctx,
             &[BufferLayout::default()],
-            &[
-                VertexAttribute::new("pos", VertexFormat::Float2),
-                VertexAttribute::new("uv0", VertexFormat::Float3),
-            ],
+            &attributes,
             default_shader,
+            PipelineParams {
+                depth_write: false,
+                color_blend: Some((
+                    Equation::Add,
+                    BlendFactor::Value(BlendValue::SourceAlpha),
+                    BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+                )),
+                ..Default::default()
+            },
+        );
+        let normal_shader = Shader::new(
+            ctx,
+            normal_shader::VERTEX,
+            normal_shader::FRAGMENT,
+            normal_shader::META,
+        )
+        .unwrap();
+        let normal_pipeline = Pipeline::with_params(
+            ctx,
+            &[BufferLayout::default()],
+            &attributes,
+            normal_shader,
             PipelineParams {
                 depth_write: false,
                 ..Default::default()
@@ -84,18 +76,95 @@ impl TextureRenderer {
 
         TextureRenderer {
             display_pipeline,
-            display_bindings,
-            vertices: vec![],
-            vertex_buffer: display_vertex_buffer,
-            time: 0.,
+            normal_pipeline,
+            textures: vec![],
+            buffers: vec![],
+            batches: vec![],
+            sprites: vec![],
         }
     }
 
+    /// Upload the image, sprites refer to it by the returned id
+    pub fn add_image(&mut self, ctx: &mut Context, image: &Image) -> TextureId {
+        let texture =
+            Texture::from_rgba8(ctx, image.width as u16, image.height as u16, &image.rgba);
+        self.add_texture(texture)
+    }
+
+    /// Use already created texture for sprites
+    pub fn add_texture(&mut self, texture: Texture) -> TextureId {
+        self.textures.push(SpriteTexture {
+            texture,
+            normal_map: None,
+        });
+        self.textures.len() - 1
+    }
+
+    /// Decode png bytes loaded at runtime and add the image
+    pub fn load_png(
+        &mut self,
+        ctx: &mut Context,
+        bytes: &[u8],
+    ) -> Result<TextureId, png::DecodingError> {
+        let image = Image::from_png(bytes)?;
+        Ok(self.add_image(ctx, &image))
+    }
+
+    pub fn texture(&self, id: TextureId) -> Texture {
+        self.textures[id].texture
+    }
+
+    pub fn normal_map(&self, id: TextureId) -> Option<Texture> {
+        self.textures[id].normal_map
+    }
+
+    /// Tangent space normal map (green is up in the image) with height in alpha, same layout as the texture
+    pub fn set_normal_map(&mut self, id: TextureId, normal_map: Option<Texture>) {
+        self.textures[id].normal_map = normal_map;
+    }
+
+    /// Upload vertices of `sprites` sorted by texture
+    pub fn update(&mut self, ctx: &mut Context) {
+        let chunks = batch_sprites(&self.sprites);
+        for (i, chunk) in chunks.iter().enumerate() {
+            if self.buffers.len() <= i {
+                self.buffers.push(StreamBuffers::new(ctx, vec![]));
+            }
+            self.buffers[i].update(ctx, &chunk.vertices, &chunk.indices);
+        }
+        self.batches = chunks.into_iter().map(|chunk| chunk.batches).collect();
+    }
+
     pub fn draw(&mut self, ctx: &mut Context, projection: Mat4) {
         ctx.apply_pipeline(&self.display_pipeline);
-        ctx.apply_bindings(&self.display_bindings);
-        ctx.apply_uniforms(&display_shader::Uniforms { projection });
-        ctx.draw(0, 6, 1);
+        for (buffers, batches) in self.buffers.iter_mut().zip(self.batches.iter()) {
+            for batch in batches {
+                buffers.bindings.images = vec![self.textures[batch.texture].texture];
+                ctx.apply_bindings(&buffers.bindings);
+                ctx.apply_uniforms(&display_shader::Uniforms { projection });
+                ctx.draw(batch.start as i32, batch.count as i32, 1);
+            }
+        }
+        ctx.end_render_pass();
+    }
+
+    /// Draw world space normals of sprites with normal maps into the pass, see `ShadowRenderer::normal_pass`
+    pub fn draw_normals(&mut self, ctx: &mut Context, projection: Mat4, pass: RenderPass) {
+        ctx.begin_pass(pass, PassAction::Nothing);
+        ctx.apply_pipeline(&self.normal_pipeline);
+        for (buffers, batches) in self.buffers.iter_mut().zip(self.batches.iter()) {
+            for batch in batches {
+                let texture = &self.textures[batch.texture];
+                let normal_map = match texture.normal_map {
+                    Some(normal_map) => normal_map,
+                    None => continue,
+                };
+                buffers.bindings.images = vec![texture.texture, normal_map];
+                ctx.apply_bindings(&buffers.bindings);
+                ctx.apply_uniforms(&normal_shader::Uniforms { projection });
+                ctx.draw(batch.start as i32, batch.count as i32, 1);
+            }
+        }
         ctx.end_render_pass();
     }
 }
@@ -106,21 +175,25 @@ mod display_shader {
     pub const VERTEX: &str = r#"#version 100
     attribute vec2 pos;
     attribute vec3 uv0;
+    attribute vec4 tint;
     varying highp vec3 uvq;
+    varying lowp vec4 color;
     
     uniform mat4 projection;
 
     void main() {
         gl_Position = projection * vec4(pos, 0, 1);
         uvq = uv0;
+        color = tint;
     }"#;
 
     pub const FRAGMENT: &str = r#"#version 100
     varying highp vec3 uvq;
+    varying lowp vec4 color;
     uniform sampler2D tex;
 
     void main() {
-        gl_FragColor = texture2D(tex, uvq.xy / uvq.z);
+        gl_FragColor = texture2D(tex, uvq.xy / uvq.z) * color;
     }"#;
 
     pub const META: ShaderMeta = ShaderMeta {
@@ -135,3 +208,59 @@ mod display_shader {
         pub projection: glam::Mat4,
     }
 }
+
+/// Vertex and Fragment shader to draw normals of sprites into the normal buffer
+/// Transparent pixels are skipped, so the ground under them is lit as usual
+mod normal_shader {
+    use miniquad::*;
+
+    pub const VERTEX: &str = r#"#version 100
+    attribute vec2 pos;
+    attribute vec3 uv0;
+    attribute vec2 tangent;
+    attribute vec2 bitangent;
+    varying highp vec3 uvq;
+    varying highp vec2 u_dir;
+    varying highp vec2 v_dir;
+
+    uniform mat4 projection;
+
+    void main() {
+        gl_Position = projection * vec4(pos, 0, 1);
+        uvq = uv0;
+        u_dir = tangent;
+        v_dir = bitangent;
+    }"#;
+
+    // xy of world normal in red and green, height in blue
+    pub const FRAGMENT: &str = r#"#version 100
+    varying highp vec3 uvq;
+    varying highp vec2 u_dir;
+    varying highp vec2 v_dir;
+    uniform sampler2D tex;
+    uniform sampler2D normal_map;
+
+    void main() {
+        highp vec2 uv = uvq.xy / uvq.z;
+        if (texture2D(tex, uv).a < 0.5) {
+            discard;
+        }
+        highp vec4 texel = texture2D(normal_map, uv);
+        highp vec3 normal = normalize(texel.xyz * 2. - 1.);
+        // green is up in the image, while v grows down
+        highp vec2 xy = normal.x * normalize(u_dir) - normal.y * normalize(v_dir);
+        gl_FragColor = vec4(xy * 0.5 + 0.5, texel.a, 1.);
+    }"#;
+
+    pub const META: ShaderMeta = ShaderMeta {
+        images: &["tex", "normal_map"],
+        uniforms: UniformBlockLayout {
+            uniforms: &[UniformDesc::new("projection", UniformType::Mat4)],
+        },
+    };
+
+    #[repr(C)]
+    pub struct Uniforms {
+        pub projection: glam::Mat4,
+    }
+}
@@ -0,0 +1,314 @@
+use crate::gi::{frame_offset, history_weight, GiParams, RadianceField, MAX_RAYS};
+use crate::sdf::SdfShape;
+use crate::world::World;
+use glam::{vec2, Mat4, Vec2};
+use miniquad::*;
+
+/// Width and height of the radiance field textures
+const FIELD_SIZE: usize = 128;
+
+/// Size of irradiance textures relative to the framebuffer, light is smooth so it can be low
+const RESOLUTION_SCALE: f32 = 0.5;
+
+#[repr(C)]
+struct Vertex {
+    pos: Vec2,
+}
+
+/// Irradiance of two frames, the new one is mixed with the old one
+struct IrradianceTargets {
+    passes: [RenderPass; 2],
+    imgs: [Texture; 2],
+}
+
+impl IrradianceTargets {
+    fn new(ctx: &mut Context, width: u32, height: u32) -> IrradianceTargets {
+        let mut texture = || {
+            Texture::new_render_texture(
+                ctx,
+                TextureParams {
+                    width,
+                    height,
+                    format: TextureFormat::RGBA8,
+                    ..Default::default()
+                },
+            )
+        };
+        let imgs = [texture(), texture()];
+        IrradianceTargets {
+            passes: [
+                RenderPass::new(ctx, imgs[0], None),
+                RenderPass::new(ctx, imgs[1], None),
+            ],
+            imgs,
+        }
+    }
+
+    fn delete(&self, ctx: &mut Context) {
+        for pass in self.passes.iter() {
+            pass.delete(ctx);
+        }
+        for img in self.imgs.iter() {
+            img.delete();
+        }
+    }
+}
+
+/// Emissive occluders and SDF shapes light the scene, light bounces off surfaces
+/// Rays are marched over `RadianceField` and accumulated over frames, see `crate::gi` for the CPU reference
+pub struct GiRenderer {
+    field: RadianceField,
+    distance_img: Texture,
+    emission_img: Texture,
+    targets: IrradianceTargets,
+    /// Target with the latest irradiance
+    current: usize,
+    /// Frames accumulated since the view changed
+    frame: usize,
+    /// History is dropped when the camera moves
+    last_projection: Option<Mat4>,
+    size: (u32, u32),
+    pipeline: Pipeline,
+    bindings: Bindings,
+    pub params: GiParams,
+}
+
+impl GiRenderer {
+    pub fn new(ctx: &mut Context) -> GiRenderer {
+        let field = RadianceField::new(FIELD_SIZE, FIELD_SIZE, (vec2(-1., -1.), vec2(1., 1.)));
+        // distances are packed into bytes and emission is taken from the nearest texel, no filtering
+        let params = TextureParams {
+            format: TextureFormat::RGBA8,
+            wrap: TextureWrap::Clamp,
+            filter: FilterMode::Nearest,
+            width: FIELD_SIZE as u32,
+            height: FIELD_SIZE as u32,
+        };
+        let distance_img = Texture::from_data_and_format(ctx, &field.distance.to_rgba8(), params);
+        let emission_img = Texture::from_data_and_format(ctx, &field.emission_rgba8(), params);
+        let size = targets_size(ctx.screen_size());
+        let targets = IrradianceTargets::new(ctx, size.0, size.1);
+
+        // huge quad in world coords, like light quads of ShadowRenderer
+        let quad_size = 1000.;
+        #[rustfmt::skip]
+        let vertices: [Vertex; 4] = [
+            Vertex { pos: vec2(-quad_size, -quad_size) },
+            Vertex { pos: vec2( quad_size, -quad_size) },
+            Vertex { pos: vec2( quad_size,  quad_size) },
+            Vertex { pos: vec2(-quad_size,  quad_size) },
+        ];
+        let vertex_buffer = Buffer::immutable(ctx, BufferType::VertexBuffer, &vertices);
+        let indices: &[u16] = &[0, 1, 2, 3, 2, 0];
+        let index_buffer = Buffer::immutable(ctx, BufferType::IndexBuffer, &indices);
+        let bindings = Bindings {
+            vertex_buffers: vec![vertex_buffer],
+            index_buffer,
+            images: vec![distance_img, emission_img, targets.imgs[0]],
+        };
+        let shader =
+            Shader::new(ctx, gi_shader::VERTEX, gi_shader::FRAGMENT, gi_shader::META).unwrap();
+        let pipeline = Pipeline::new(
+            ctx,
+            &[BufferLayout::default()],
+            &[VertexAttribute::new("pos", VertexFormat::Float2)],
+            shader,
+        );
+        GiRenderer {
+            field,
+            distance_img,
+            emission_img,
+            targets,
+            current: 0,
+            frame: 0,
+            last_projection: None,
+            size,
+            pipeline,
+            bindings,
+            params: GiParams::default(),
+        }
+    }
+
+    /// Recreate irradiance textures for new framebuffer size
+    pub fn resize(&mut self, ctx: &mut Context, width: f32, height: f32) {
+        self.targets.delete(ctx);
+        self.size = targets_size((width, height));
+        self.targets = IrradianceTargets::new(ctx, self.size.0, self.size.1);
+        self.frame = 0;
+    }
+
+    /// Bake occluders and shapes into the radiance field
+    pub fn update(&mut self, ctx: &mut Context, world: &World, shapes: &[SdfShape]) {
+        self.field.bake(world, shapes);
+        self.distance_img
+            .update(ctx, &self.field.distance.to_rgba8());
+        self.emission_img.update(ctx, &self.field.emission_rgba8());
+    }
+
+    /// Gather light of this frame and mix it into the accumulated irradiance
+    pub fn draw(&mut self, ctx: &mut Context, projection: Mat4) {
+        if self.last_projection != Some(projection) {
+            self.last_projection = Some(projection);
+            self.frame = 0;
+        }
+        let next = 1 - self.current;
+        self.bindings.images[2] = self.targets.imgs[self.current];
+        ctx.begin_pass(self.targets.passes[next], PassAction::Nothing);
+        ctx.apply_pipeline(&self.pipeline);
+        ctx.apply_bindings(&self.bindings);
+        let field = &self.field.distance;
+        let texel = field.texel_size();
+        ctx.apply_uniforms(&gi_shader::Uniforms {
+            projection,
+            rect_min: field.rect.0,
+            rect_size: field.rect.1 - field.rect.0,
+            max_distance: field.max_distance(),
+            texel: texel.x().max(texel.y()),
+            size: vec2(self.size.0 as f32, self.size.1 as f32),
+            rays: self.params.rays.max(1).min(MAX_RAYS) as f32,
+            bounce: self.params.bounce,
+            intensity: self.params.intensity,
+            offset: frame_offset(self.frame),
+            weight: history_weight(self.frame),
+        });
+        ctx.draw(0, 6, 1);
+        ctx.end_render_pass();
+        self.current = next;
+        self.frame += 1;
+    }
+
+    /// Accumulated irradiance in screen space, see `ShadowRenderer::set_irradiance`
+    pub fn irradiance_img(&self) -> Texture {
+        self.targets.imgs[self.current]
+    }
+}
+
+fn targets_size(screen_size: (f32, f32)) -> (u32, u32) {
+    (
+        ((screen_size.0 * RESOLUTION_SCALE) as u32).max(1),
+        ((screen_size.1 * RESOLUTION_SCALE) as u32).max(1),
+    )
+}
+
+/// Vertex and Fragment shader to gather light by ray marching the radiance field
+mod gi_shader {
+    use glam::Vec2;
+    use miniquad::*;
+
+    pub const VERTEX: &str = r#"#version 100
+    attribute vec2 pos;
+    varying highp vec2 fpos;
+
+    uniform mat4 projection;
+
+    void main() {
+        gl_Position = projection * vec4(pos, 0, 1);
+        fpos = pos;
+    }"#;
+
+    // mirrors RadianceField::irradiance, loop limits are gi::MAX_RAYS and gi::MAX_STEPS
+    // distance is packed into red and green channels, see DistanceField::to_rgba8
+    pub const FRAGMENT: &str = r#"#version 100
+    uniform sampler2D distance_field;
+    uniform sampler2D emission_field;
+    uniform sampler2D history;
+    uniform highp mat4 projection;
+    uniform highp vec2 rect_min;
+    uniform highp vec2 rect_size;
+    uniform highp float max_distance;
+    uniform highp float texel;
+    uniform highp vec2 size;
+    uniform highp float rays;
+    uniform highp float bounce;
+    uniform highp float intensity;
+    uniform highp float offset;
+    uniform highp float weight;
+
+    varying highp vec2 fpos;
+
+    highp vec2 field_uv(highp vec2 p) {
+        return clamp((p - rect_min) / rect_size, 0., 1.);
+    }
+
+    highp float sample_distance(highp vec2 p) {
+        highp vec2 rg = floor(texture2D(distance_field, field_uv(p)).rg * 255. + 0.5);
+        return ((rg.x * 256. + rg.y) / 65535. - 0.5) * 2. * max_distance;
+    }
+
+    // irradiance of the last frame at world point, nothing outside of the screen
+    highp vec3 previous(highp vec2 p) {
+        highp vec2 uv = (projection * vec4(p, 0., 1.)).xy * 0.5 + 0.5;
+        if (uv.x < 0. || uv.y < 0. || uv.x > 1. || uv.y > 1.) {
+            return vec3(0.);
+        }
+        return texture2D(history, uv).rgb;
+    }
+
+    void main() {
+        highp vec3 light = vec3(0.);
+        if (sample_distance(fpos) < 0.) {
+            light = texture2D(emission_field, field_uv(fpos)).rgb * intensity;
+        } else {
+            // pixels get different rays, accumulated frames average them
+            highp float noise = fract(sin(dot(gl_FragCoord.xy, vec2(12.9898, 78.233))) * 43758.5453);
+            for (int i = 0; i < 16; i++) {
+                if (float(i) >= rays) {
+                    break;
+                }
+                highp float angle = (float(i) + fract(offset + noise)) * 6.2831853 / rays;
+                highp vec2 dir = vec2(cos(angle), sin(angle));
+                highp float t = texel;
+                for (int j = 0; j < 32; j++) {
+                    highp vec2 p = fpos + dir * t;
+                    highp float h = sample_distance(p);
+                    if (h < 0.5 * texel) {
+                        light += texture2D(emission_field, field_uv(p)).rgb
+                            + previous(p - dir * 2. * texel) * bounce;
+                        break;
+                    }
+                    t += h;
+                    if (t > max_distance) {
+                        break;
+                    }
+                }
+            }
+            light = light / rays * intensity;
+        }
+        highp vec3 old = texture2D(history, gl_FragCoord.xy / size).rgb;
+        gl_FragColor = vec4(mix(old, light, weight), 1.);
+    }"#;
+
+    pub const META: ShaderMeta = ShaderMeta {
+        images: &["distance_field", "emission_field", "history"],
+        uniforms: UniformBlockLayout {
+            uniforms: &[
+                UniformDesc::new("projection", UniformType::Mat4),
+                UniformDesc::new("rect_min", UniformType::Float2),
+                UniformDesc::new("rect_size", UniformType::Float2),
+                UniformDesc::new("max_distance", UniformType::Float1),
+                UniformDesc::new("texel", UniformType::Float1),
+                UniformDesc::new("size", UniformType::Float2),
+                UniformDesc::new("rays", UniformType::Float1),
+                UniformDesc::new("bounce", UniformType::Float1),
+                UniformDesc::new("intensity", UniformType::Float1),
+                UniformDesc::new("offset", UniformType::Float1),
+                UniformDesc::new("weight", UniformType::Float1),
+            ],
+        },
+    };
+
+    #[repr(C)]
+    pub struct Uniforms {
+        pub projection: glam::Mat4,
+        pub rect_min: Vec2,
+        pub rect_size: Vec2,
+        pub max_distance: f32,
+        pub texel: f32,
+        pub size: Vec2,
+        pub rays: f32,
+        pub bounce: f32,
+        pub intensity: f32,
+        pub offset: f32,
+        pub weight: f32,
+    }
+}
@@ -0,0 +1,333 @@
+use crate::jump_flood::steps;
+use glam::{vec2, Vec2};
+use miniquad::*;
+
+#[repr(C)]
+struct Vertex {
+    pos: Vec2,
+}
+
+/// Binary occluder mask, ping-pong seed textures and the resulting distance field
+struct FloodTargets {
+    mask_pass: RenderPass,
+    mask_img: Texture,
+    seed_passes: [RenderPass; 2],
+    seed_imgs: [Texture; 2],
+    field_pass: RenderPass,
+    field_img: Texture,
+}
+
+impl FloodTargets {
+    fn new(ctx: &mut Context, width: u32, height: u32) -> FloodTargets {
+        // seeds and distances are packed into bytes, so they can't be filtered
+        let mut texture = || {
+            Texture::new_render_texture(
+                ctx,
+                TextureParams {
+                    width,
+                    height,
+                    format: TextureFormat::RGBA8,
+                    filter: FilterMode::Nearest,
+                    ..Default::default()
+                },
+            )
+        };
+        let mask_img = texture();
+        let seed_imgs = [texture(), texture()];
+        let field_img = texture();
+        FloodTargets {
+            mask_pass: RenderPass::new(ctx, mask_img, None),
+            mask_img,
+            seed_passes: [
+                RenderPass::new(ctx, seed_imgs[0], None),
+                RenderPass::new(ctx, seed_imgs[1], None),
+            ],
+            seed_imgs,
+            field_pass: RenderPass::new(ctx, field_img, None),
+            field_img,
+        }
+    }
+
+    fn delete(&self, ctx: &mut Context) {
+        self.mask_pass.delete(ctx);
+        self.field_pass.delete(ctx);
+        for pass in self.seed_passes.iter() {
+            pass.delete(ctx);
+        }
+        self.mask_img.delete();
+        self.field_img.delete();
+        for img in self.seed_imgs.iter() {
+            img.delete();
+        }
+    }
+}
+
+/// Signed distance field of whatever is drawn into the occluder mask (polygons, sprites, text)
+/// computed on GPU by jump flooding, `crate::jump_flood` is the CPU reference of the same passes
+pub struct JumpFlood {
+    targets: FloodTargets,
+    size: (u32, u32),
+    seed_pipeline: Pipeline,
+    flood_pipeline: Pipeline,
+    resolve_pipeline: Pipeline,
+    /// Fullscreen quad, images are switched between passes
+    bindings: Bindings,
+}
+
+impl JumpFlood {
+    pub fn new(ctx: &mut Context, width: u32, height: u32) -> JumpFlood {
+        let targets = FloodTargets::new(ctx, width, height);
+        #[rustfmt::skip]
+        let vertices: [Vertex; 4] = [
+            Vertex { pos: vec2(-1., -1.) },
+            Vertex { pos: vec2( 1., -1.) },
+            Vertex { pos: vec2( 1.,  1.) },
+            Vertex { pos: vec2(-1.,  1.) },
+        ];
+        let vertex_buffer = Buffer::immutable(ctx, BufferType::VertexBuffer, &vertices);
+        let indices: &[u16] = &[0, 1, 2, 3, 2, 0];
+        let index_buffer = Buffer::immutable(ctx, BufferType::IndexBuffer, &indices);
+        let bindings = Bindings {
+            vertex_buffers: vec![vertex_buffer],
+            index_buffer,
+            images: vec![],
+        };
+        let mut pipeline = |fragment, meta| {
+            let shader = Shader::new(ctx, jump_flood_shader::VERTEX, fragment, meta).unwrap();
+            Pipeline::new(
+                ctx,
+                &[BufferLayout::default()],
+                &[VertexAttribute::new("pos", VertexFormat::Float2)],
+                shader,
+            )
+        };
+        let seed_pipeline = pipeline(jump_flood_shader::SEED, jump_flood_shader::SEED_META);
+        let flood_pipeline = pipeline(jump_flood_shader::FLOOD, jump_flood_shader::FLOOD_META);
+        let resolve_pipeline =
+            pipeline(jump_flood_shader::RESOLVE, jump_flood_shader::RESOLVE_META);
+        JumpFlood {
+            targets,
+            size: (width, height),
+            seed_pipeline,
+            flood_pipeline,
+            resolve_pipeline,
+            bindings,
+        }
+    }
+
+    /// Recreate textures for the new size, the mask has to be drawn again
+    pub fn resize(&mut self, ctx: &mut Context, width: u32, height: u32) {
+        if self.size == (width, height) {
+            return;
+        }
+        self.targets.delete(ctx);
+        self.targets = FloodTargets::new(ctx, width, height);
+        self.size = (width, height);
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    /// Pass of the occluder mask: pixels with red above 0.5 are occluders
+    /// Clear it with `clear_mask` and draw occluders in white with any pipeline
+    pub fn mask_pass(&self) -> RenderPass {
+        self.targets.mask_pass
+    }
+
+    pub fn clear_mask(&self, ctx: &mut Context) {
+        ctx.begin_pass(
+            self.targets.mask_pass,
+            PassAction::clear_color(0., 0., 0., 0.),
+        );
+        ctx.end_render_pass();
+    }
+
+    /// Largest stored distance in pixels, the diagonal of the mask
+    pub fn max_distance(&self) -> f32 {
+        vec2(self.size.0 as f32, self.size.1 as f32).length()
+    }
+
+    /// Distance field of the mask in pixels, packed like `DistanceField::to_rgba8` with `max_distance` range
+    pub fn distance_field_img(&self) -> Texture {
+        self.targets.field_img
+    }
+
+    /// Find boundary seeds of the mask, flood them with halving steps and resolve signed distances
+    pub fn run(&mut self, ctx: &mut Context) {
+        let size = vec2(self.size.0 as f32, self.size.1 as f32);
+        // seeds
+        self.bindings.images = vec![self.targets.mask_img];
+        ctx.begin_pass(self.targets.seed_passes[0], PassAction::Nothing);
+        ctx.apply_pipeline(&self.seed_pipeline);
+        ctx.apply_bindings(&self.bindings);
+        ctx.apply_uniforms(&jump_flood_shader::SeedUniforms { size });
+        ctx.draw(0, 6, 1);
+        ctx.end_render_pass();
+        // flood, ping-pong between seed textures
+        let mut current = 0;
+        for step in steps(self.size.0 as usize, self.size.1 as usize) {
+            self.bindings.images = vec![self.targets.seed_imgs[current]];
+            ctx.begin_pass(self.targets.seed_passes[1 - current], PassAction::Nothing);
+            ctx.apply_pipeline(&self.flood_pipeline);
+            ctx.apply_bindings(&self.bindings);
+            ctx.apply_uniforms(&jump_flood_shader::FloodUniforms {
+                size,
+                jump: step as f32,
+            });
+            ctx.draw(0, 6, 1);
+            ctx.end_render_pass();
+            current = 1 - current;
+        }
+        // signed distances
+        self.bindings.images = vec![self.targets.seed_imgs[current], self.targets.mask_img];
+        ctx.begin_pass(self.targets.field_pass, PassAction::Nothing);
+        ctx.apply_pipeline(&self.resolve_pipeline);
+        ctx.apply_bindings(&self.bindings);
+        ctx.apply_uniforms(&jump_flood_shader::ResolveUniforms {
+            size,
+            max_distance: self.max_distance(),
+        });
+        ctx.draw(0, 6, 1);
+        ctx.end_render_pass();
+    }
+}
+
+/// Shaders of jump flooding passes, all of them draw the fullscreen quad
+/// Seed pixel coordinates are packed into 16 bits: x into red and green, y into blue and alpha, 65535 -- no seed
+mod jump_flood_shader {
+    use glam::Vec2;
+    use miniquad::*;
+
+    pub const VERTEX: &str = r#"#version 100
+    attribute vec2 pos;
+
+    void main() {
+        gl_Position = vec4(pos, 0, 1);
+    }"#;
+
+    // mask is clamped, so edges of the texture are not a boundary
+    pub const SEED: &str = r#"#version 100
+    uniform sampler2D mask;
+    uniform highp vec2 size;
+
+    bool occluder(highp vec2 pixel) {
+        return texture2D(mask, clamp(pixel, vec2(0.5), size - 0.5) / size).r > 0.5;
+    }
+
+    void main() {
+        highp vec2 pixel = gl_FragCoord.xy;
+        bool inside = occluder(pixel);
+        bool boundary = occluder(pixel + vec2(-1., 0.)) != inside
+            || occluder(pixel + vec2(1., 0.)) != inside
+            || occluder(pixel + vec2(0., -1.)) != inside
+            || occluder(pixel + vec2(0., 1.)) != inside;
+        if (boundary) {
+            highp vec2 seed = floor(pixel);
+            highp vec2 hi = floor(seed / 256.);
+            gl_FragColor = vec4(hi.x, seed.x - hi.x * 256., hi.y, seed.y - hi.y * 256.) / 255.;
+        } else {
+            gl_FragColor = vec4(1.);
+        }
+    }"#;
+
+    // neighbours are checked in the same order as in jump_flood::flood_pass
+    pub const FLOOD: &str = r#"#version 100
+    uniform sampler2D seeds;
+    uniform highp vec2 size;
+    uniform highp float jump;
+
+    void main() {
+        highp vec2 pixel = gl_FragCoord.xy;
+        highp vec4 best = vec4(1.);
+        highp float best_distance = 1e20;
+        for (int j = -1; j <= 1; j++) {
+            for (int i = -1; i <= 1; i++) {
+                highp vec2 p = pixel + vec2(float(i), float(j)) * jump;
+                if (p.x < 0. || p.y < 0. || p.x > size.x || p.y > size.y) {
+                    continue;
+                }
+                highp vec4 texel = texture2D(seeds, p / size);
+                highp vec4 bytes = floor(texel * 255. + 0.5);
+                highp vec2 seed = vec2(bytes.r * 256. + bytes.g, bytes.b * 256. + bytes.a);
+                if (seed.x > 65534.) {
+                    continue;
+                }
+                highp float dst = length(seed + 0.5 - pixel);
+                if (dst < best_distance) {
+                    best = texel;
+                    best_distance = dst;
+                }
+            }
+        }
+        gl_FragColor = best;
+    }"#;
+
+    // distance is packed into red and green channels, same as DistanceField::to_rgba8
+    pub const RESOLVE: &str = r#"#version 100
+    uniform sampler2D seeds;
+    uniform sampler2D mask;
+    uniform highp vec2 size;
+    uniform highp float max_distance;
+
+    void main() {
+        highp vec2 pixel = gl_FragCoord.xy;
+        highp vec4 bytes = floor(texture2D(seeds, pixel / size) * 255. + 0.5);
+        highp vec2 seed = vec2(bytes.r * 256. + bytes.g, bytes.b * 256. + bytes.a);
+        highp float dst = max_distance;
+        if (seed.x < 65535.) {
+            dst = length(seed + 0.5 - pixel);
+        }
+        if (texture2D(mask, pixel / size).r > 0.5) {
+            dst = -dst;
+        }
+        highp float normalized = clamp(dst / max_distance * 0.5 + 0.5, 0., 1.);
+        highp float value = floor(normalized * 65535. + 0.5);
+        highp float hi = floor(value / 256.);
+        gl_FragColor = vec4(hi / 255., (value - hi * 256.) / 255., 0., 1.);
+    }"#;
+
+    pub const SEED_META: ShaderMeta = ShaderMeta {
+        images: &["mask"],
+        uniforms: UniformBlockLayout {
+            uniforms: &[UniformDesc::new("size", UniformType::Float2)],
+        },
+    };
+
+    pub const FLOOD_META: ShaderMeta = ShaderMeta {
+        images: &["seeds"],
+        uniforms: UniformBlockLayout {
+            uniforms: &[
+                UniformDesc::new("size", UniformType::Float2),
+                UniformDesc::new("jump", UniformType::Float1),
+            ],
+        },
+    };
+
+    pub const RESOLVE_META: ShaderMeta = ShaderMeta {
+        images: &["seeds", "mask"],
+        uniforms: UniformBlockLayout {
+            uniforms: &[
+                UniformDesc::new("size", UniformType::Float2),
+                UniformDesc::new("max_distance", UniformType::Float1),
+            ],
+        },
+    };
+
+    #[repr(C)]
+    pub struct SeedUniforms {
+        pub size: Vec2,
+    }
+
+    #[repr(C)]
+    pub struct FloodUniforms {
+        pub size: Vec2,
+        pub jump: f32,
+    }
+
+    #[repr(C)]
+    pub struct ResolveUniforms {
+        pub size: Vec2,
+        pub max_distance: f32,
+    }
+}
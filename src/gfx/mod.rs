@@ -1,6 +1,14 @@
 /// Deformed texture rendering using projection textures
 pub mod deformed_texture;
-/// Shadows using offscreen pipeline and trick with projection textures(not fair shadows)
+/// Antialiased signed distance shapes drawn by one generated shader
+pub mod sdf;
+/// Global illumination of emissive surfaces gathered on GPU and accumulated over frames
+pub mod gi;
+/// Distance field of an occluder mask on GPU by jump flooding with ping-pong render passes
+pub mod jump_flood;
+/// Soft shadows using offscreen pipeline, shadow volumes and penumbra wedges
 pub mod shadow;
-/// Simple triangle antialiasing using signed distance filed
-pub mod triangle_sdf;
+/// CPU rasterizer of shadow masks, tests the shadow pipeline without GPU
+pub mod software;
+/// Vertex and index buffers which grow with the geometry
+pub mod stream_buffers;
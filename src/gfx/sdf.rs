@@ -0,0 +1,185 @@
+use super::stream_buffers::StreamBuffers;
+use crate::sdf::{
+    max_smoothing, scene_bounds, scene_glsl, scene_params, SdfShape, MAX_SDF_PARAMS,
+    SDF_GLSL_LIBRARY,
+};
+use crate::transform::Transform2D;
+use glam::{vec2, Mat4, Vec2};
+use miniquad::*;
+use std::collections::HashMap;
+
+#[repr(C)]
+struct Vertex {
+    pos: Vec2,
+}
+
+/// Why shapes can't be drawn, the renderer keeps the previous shapes then
+#[derive(Debug)]
+pub enum SdfError {
+    /// Shapes need more vec4 parameters than `MAX_SDF_PARAMS`
+    TooManyParams(usize),
+    /// Generated shader doesn't compile
+    Shader(ShaderError),
+}
+
+/// Draws all SDF shapes with one shader generated from kinds of the shapes
+pub struct SdfRenderer {
+    /// Pipelines by generated scene function, miniquad can't delete shaders,
+    /// so every function is compiled once and the shapes are passed in uniforms
+    pipelines: HashMap<String, Pipeline>,
+    /// Scene function of the current shapes
+    scene: String,
+    params: [[f32; 4]; MAX_SDF_PARAMS],
+    buffers: StreamBuffers,
+    /// Threshold for smoothstep in fragment shader
+    pub sdf_edge: f32,
+    shapes: Vec<SdfShape>,
+}
+
+impl SdfRenderer {
+    pub fn new(ctx: &mut Context) -> SdfRenderer {
+        let mut renderer = SdfRenderer {
+            pipelines: HashMap::new(),
+            scene: String::new(),
+            params: [[0.; 4]; MAX_SDF_PARAMS],
+            buffers: StreamBuffers::new(ctx, vec![]),
+            sdf_edge: 0.01,
+            shapes: vec![],
+        };
+        let shape = SdfShape {
+            transform: Transform2D::from_translation(vec2(1., -1.)),
+            ..SdfShape::default()
+        };
+        renderer.set_shapes(ctx, vec![shape]).unwrap();
+        renderer
+    }
+
+    pub fn shapes(&self) -> &[SdfShape] {
+        &self.shapes
+    }
+
+    /// Replace shapes, the shader is compiled only for new kinds of shapes or ops,
+    /// moved or recolored shapes just update uniforms
+    pub fn set_shapes(&mut self, ctx: &mut Context, shapes: Vec<SdfShape>) -> Result<(), SdfError> {
+        let params = scene_params(&shapes);
+        if params.len() > MAX_SDF_PARAMS {
+            return Err(SdfError::TooManyParams(params.len()));
+        }
+        let scene = scene_glsl(&shapes);
+        if !self.pipelines.contains_key(&scene) {
+            let pipeline = display_pipeline(ctx, &scene).map_err(SdfError::Shader)?;
+            self.pipelines.insert(scene.clone(), pipeline);
+        }
+        self.params = [[0.; 4]; MAX_SDF_PARAMS];
+        self.params[..params.len()].copy_from_slice(&params);
+        self.scene = scene;
+        self.shapes = shapes;
+        Ok(())
+    }
+
+    pub fn draw(&mut self, ctx: &mut Context, projection: Mat4) {
+        // quad around the shapes, fragment shader do the rest
+        let margin = self.sdf_edge + max_smoothing(&self.shapes);
+        let (min, max) = match scene_bounds(&self.shapes, margin) {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        let vertices = [
+            Vertex { pos: min },
+            Vertex {
+                pos: vec2(max.x(), min.y()),
+            },
+            Vertex { pos: max },
+            Vertex {
+                pos: vec2(min.x(), max.y()),
+            },
+        ];
+        self.buffers.update(ctx, &vertices, &[0, 1, 2, 2, 3, 0]);
+        ctx.apply_pipeline(&self.pipelines[&self.scene]);
+        ctx.apply_bindings(&self.buffers.bindings);
+        ctx.apply_uniforms(&display_shader::Uniforms {
+            projection,
+            sdf_edge: self.sdf_edge,
+            params: self.params,
+        });
+        ctx.draw(0, 6, 1);
+        ctx.end_render_pass();
+    }
+}
+
+fn display_pipeline(ctx: &mut Context, scene: &str) -> Result<Pipeline, ShaderError> {
+    let fragment = format!(
+        "{}{}{}{}",
+        display_shader::FRAGMENT_HEADER,
+        SDF_GLSL_LIBRARY,
+        scene,
+        display_shader::FRAGMENT_MAIN
+    );
+    let shader = Shader::new(ctx, display_shader::VERTEX, &fragment, display_shader::META)?;
+    Ok(Pipeline::with_params(
+        ctx,
+        &[BufferLayout::default()],
+        &[VertexAttribute::new("pos", VertexFormat::Float2)],
+        shader,
+        PipelineParams {
+            depth_write: false,
+            color_blend: Some((
+                Equation::Add,
+                BlendFactor::Value(BlendValue::SourceAlpha),
+                BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+            )),
+            ..Default::default()
+        },
+    ))
+}
+
+mod display_shader {
+    use crate::sdf::MAX_SDF_PARAMS;
+    use miniquad::*;
+
+    pub const VERTEX: &str = r#"#version 100
+    attribute vec2 pos;
+    varying highp vec2 fpos;
+
+    uniform mat4 projection;
+
+    void main() {
+        gl_Position = projection * vec4(pos, 0, 1);
+        fpos = pos;
+    }"#;
+
+    pub const FRAGMENT_HEADER: &str = r#"#version 100
+    precision highp float;
+    varying highp vec2 fpos;
+    uniform float sdf_edge;
+"#;
+
+    pub const FRAGMENT_MAIN: &str = r#"
+    void main() {
+        vec3 color;
+        float d = scene(fpos, color);
+        float res = 0.;
+        if (d > 0.) {
+            res = smoothstep(0., sdf_edge, d);
+        }
+        gl_FragColor = vec4(color, 1. - res);
+    }"#;
+
+    pub const META: ShaderMeta = ShaderMeta {
+        images: &[],
+        uniforms: UniformBlockLayout {
+            uniforms: &[
+                UniformDesc::new("projection", UniformType::Mat4),
+                UniformDesc::new("sdf_edge", UniformType::Float1),
+                UniformDesc::new("sdf_params", UniformType::Float4).array(MAX_SDF_PARAMS),
+            ],
+        },
+    };
+
+    #[repr(C)]
+    pub struct Uniforms {
+        pub projection: glam::Mat4,
+        pub sdf_edge: f32,
+        pub params: [[f32; 4]; MAX_SDF_PARAMS],
+    }
+}
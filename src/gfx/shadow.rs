@@ -1,13 +1,29 @@
-use crate::geometry::projective_textures;
+use super::jump_flood::JumpFlood;
+use super::stream_buffers::StreamBuffers;
+use crate::distance_field::DistanceField;
+use crate::geometry::nonzero_direction;
+use crate::light::{Light, LightKind};
+use crate::world::World;
 use glam::{vec2, Mat4, Vec2, Vec3};
 use miniquad::*;
 
-/// Max number of shadow vertices (#shadows = MAX_SHADOW_VERTICES / 4)
-const MAX_SHADOW_VERTICES_BYTES: usize = 1000;
-/// Max number of shadow indices (#shadows = MAX_SHADOW_INDICES / 6)
-const MAX_SHADOW_INDICES_BYTES: usize = 1500;
+/// Max number of shadow vertices in one draw call, indices are u16 (#shadow triangles = MAX_BATCH_VERTICES / 3)
+const MAX_BATCH_VERTICES: usize = u16::MAX as usize;
 
-const TEXTURE_SIZE: u32 = 1024;
+/// Default size of offscreen textures relative to the framebuffer
+const DEFAULT_RESOLUTION_SCALE: f32 = 1.;
+
+/// Width and height of the occluders' distance field texture
+const DISTANCE_FIELD_SIZE: usize = 128;
+
+/// How shadow masks are rendered
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShadowMode {
+    /// Shadow volumes and penumbra wedges
+    Geometry,
+    /// Sphere tracing of occluders' distance field towards the light
+    DistanceField,
+}
 
 #[repr(C)]
 struct ShadowVertex {
@@ -26,83 +42,186 @@ struct Vertex {
     uv: Vec2,
 }
 
+/// Offscreen passes, their size follows the framebuffer
+struct RenderTargets {
+    /// Shadow mask of one light
+    offscreen_pass: RenderPass,
+    mask_img: Texture,
+    /// All lights together
+    light_pass: RenderPass,
+    light_img: Texture,
+    depth_imgs: [Texture; 2],
+    /// Normals of sprites (xy in red and green), height in blue, alpha -- sprite is there
+    normal_pass: RenderPass,
+    normal_img: Texture,
+}
+
+impl RenderTargets {
+    fn new(ctx: &mut Context, width: u32, height: u32) -> RenderTargets {
+        let mut texture = |format| {
+            Texture::new_render_texture(
+                ctx,
+                TextureParams {
+                    width,
+                    height,
+                    format,
+                    ..Default::default()
+                },
+            )
+        };
+        let mask_img = texture(TextureFormat::RGBA8);
+        let light_img = texture(TextureFormat::RGBA8);
+        let depth_imgs = [texture(TextureFormat::Depth), texture(TextureFormat::Depth)];
+        let normal_img = texture(TextureFormat::RGBA8);
+        RenderTargets {
+            offscreen_pass: RenderPass::new(ctx, mask_img, depth_imgs[0]),
+            mask_img,
+            light_pass: RenderPass::new(ctx, light_img, depth_imgs[1]),
+            light_img,
+            depth_imgs,
+            normal_pass: RenderPass::new(ctx, normal_img, None),
+            normal_img,
+        }
+    }
+
+    fn delete(&self, ctx: &mut Context) {
+        self.offscreen_pass.delete(ctx);
+        self.light_pass.delete(ctx);
+        self.normal_pass.delete(ctx);
+        self.mask_img.delete();
+        self.light_img.delete();
+        self.normal_img.delete();
+        for img in self.depth_imgs.iter() {
+            img.delete();
+        }
+    }
+}
+
 pub struct ShadowRenderer {
     // offscreen pipeline -- render shadow map into texture
     offscreen_pipeline: Pipeline,
-    offscreen_bindings: Bindings,
+    /// Shadow geometry split into draw calls
+    batches: Vec<StreamBuffers>,
     offscreen_light_pipeline: Pipeline,
     offscreen_light_bindings: Bindings,
-    offscreen_pass: RenderPass,
-    // display pipeline -- process shadow map and draw scene
+    targets: RenderTargets,
+    /// Size of offscreen textures relative to the framebuffer
+    resolution_scale: f32,
+    /// Framebuffer size
+    screen_size: (f32, f32),
+    // accumulate pipeline -- add light of each shadow map into light buffer
+    accumulate_pipeline: Pipeline,
+    accumulate_bindings: Bindings,
+    // irradiance pipeline -- add global illumination into light buffer
+    irradiance_pipeline: Pipeline,
+    irradiance_bindings: Bindings,
+    /// Added to the light buffer on top of the lights
+    irradiance: Option<Texture>,
+    // display pipeline -- multiply scene by light buffer
     display_pipeline: Pipeline,
     display_bindings: Bindings,
-    shadows: Vec<[Vec2; 4]>,
+    /// (first vertex, number of vertices) of each light's shadows in `vertices`
+    shadow_ranges: Vec<(usize, usize)>,
     vertices: Vec<ShadowVertex>,
     indices: Vec<u16>,
-    vertex_buffer: Buffer,
-    index_buffer: Buffer,
+    /// Sharpness of penumbra, 1 -- physically based width
     pub shadow_smooth_th: f32,
-    pub light_pos: Vec2,
-    pub light_size: f32,
+    /// Height of lights above the ground for lighting of normal mapped sprites
+    pub light_height: f32,
+    /// Height of the highest point of normal mapped sprites
+    pub sprite_height: f32,
+    pub mode: ShadowMode,
+    /// Baked on CPU in `DistanceField` mode
+    distance_field: DistanceField,
+    distance_field_img: Texture,
+    distance_field_pipeline: Pipeline,
+    distance_field_bindings: Bindings,
+    /// Distance field of arbitrary occluder masks at the resolution of the offscreen pass
+    jump_flood: JumpFlood,
 }
 
 impl ShadowRenderer {
-    /// Use this method to add shadow geometry(4 vertices)
-    pub fn push_shadow(&mut self, shadow: [Vec2; 4]) {
-        self.shadows.push(shadow);
+    /// Update rendering pipeline with shadows computed by `World::compute_shadows`
+    pub fn reconstruct_buffers(&mut self, ctx: &mut Context, world: &World) {
+        self.vertices.clear();
+        self.shadow_ranges.clear();
+        for shadows in (0..world.lights.len()).map(|i| world.shadows(i)) {
+            let start = self.vertices.len();
+            self.vertices
+                .extend(shadows.iter().map(|point| ShadowVertex {
+                    pos: point.pos,
+                    normal: point.normal,
+                }));
+            self.shadow_ranges
+                .push((start, self.vertices.len() - start));
+        }
+        // vertices are not shared between triangles, so indices of every batch are 0, 1, 2, ...
+        let batch_size = self.vertices.len().min(MAX_BATCH_VERTICES);
+        if self.indices.len() < batch_size {
+            self.indices = (0..batch_size).map(|i| i as u16).collect();
+        }
+        for (i, batch) in self.vertices.chunks(MAX_BATCH_VERTICES).enumerate() {
+            if self.batches.len() <= i {
+                self.batches.push(StreamBuffers::new(ctx, vec![]));
+            }
+            self.batches[i].update(ctx, batch, &self.indices[..batch.len()]);
+        }
+        if self.mode == ShadowMode::DistanceField {
+            self.distance_field.bake(world);
+            self.distance_field_img
+                .update(ctx, &self.distance_field.to_rgba8());
+        }
     }
 
-    /// Remove all shadows pushed in by push_shadow
-    pub fn clear_shadows_buffer(&mut self) {
-        self.shadows.clear()
+    /// Recreate offscreen textures for new framebuffer size
+    pub fn resize(&mut self, ctx: &mut Context, width: f32, height: f32) {
+        self.screen_size = (width, height);
+        self.targets.delete(ctx);
+        let (width, height) = self.targets_size();
+        self.targets = RenderTargets::new(ctx, width, height);
+        self.jump_flood.resize(ctx, width, height);
+        self.accumulate_bindings.images = vec![self.targets.mask_img, self.targets.normal_img];
+        self.display_bindings.images[0] = self.targets.light_img;
     }
 
-    /// Update rendering pipeline with new shadows
-    pub fn reconstruct_buffers(&mut self, ctx: &mut Context) {
-        self.vertices.clear();
-        self.indices.clear();
-        self.vertices.shrink_to_fit();
-        self.indices.shrink_to_fit();
-        let normals = [vec2(1., 0.), vec2(1., 1.), vec2(0., 1.), vec2(0., 0.)];
-        for shadow in self.shadows.iter() {
-            let newi = vec![0, 1, 2, 3, 2, 0];
-            // new indices start from "shift" position
-            let shift = self.vertices.len();
-            for idx in newi.iter() {
-                self.indices.push((shift + *idx) as u16);
-            }
+    /// Draw occluders into its mask and `run` it to get their distance field
+    pub fn jump_flood(&mut self) -> &mut JumpFlood {
+        &mut self.jump_flood
+    }
 
-            let projective_normals = projective_textures(shadow, &normals);
-            for (v, n) in shadow.iter().zip(projective_normals.iter()) {
-                self.vertices.push(ShadowVertex {
-                    pos: *v,
-                    normal: *n,
-                });
-            }
-        }
-        self.vertex_buffer.update(ctx, &self.vertices);
-        self.index_buffer.update(ctx, &self.indices);
+    /// Light in screen space added to every pixel of the light buffer, e.g. `GiRenderer::irradiance_img`
+    pub fn set_irradiance(&mut self, irradiance: Option<Texture>) {
+        self.irradiance = irradiance;
+    }
+
+    pub fn resolution_scale(&self) -> f32 {
+        self.resolution_scale
+    }
+
+    /// Change size of offscreen textures relative to the framebuffer
+    pub fn set_resolution_scale(&mut self, ctx: &mut Context, scale: f32) {
+        self.resolution_scale = scale;
+        let (width, height) = self.screen_size;
+        self.resize(ctx, width, height);
+    }
+
+    fn targets_size(&self) -> (u32, u32) {
+        let (width, height) = self.screen_size;
+        (
+            ((width * self.resolution_scale) as u32).max(1),
+            ((height * self.resolution_scale) as u32).max(1),
+        )
     }
 
     pub fn new(ctx: &mut Context) -> ShadowRenderer {
-        let color_img = Texture::new_render_texture(
-            ctx,
-            TextureParams {
-                width: TEXTURE_SIZE,
-                height: TEXTURE_SIZE,
-                format: TextureFormat::RGBA8,
-                ..Default::default()
-            },
-        );
-        let depth_img = Texture::new_render_texture(
-            ctx,
-            TextureParams {
-                width: TEXTURE_SIZE,
-                height: TEXTURE_SIZE,
-                format: TextureFormat::Depth,
-                ..Default::default()
-            },
+        let screen_size = ctx.screen_size();
+        let resolution_scale = DEFAULT_RESOLUTION_SCALE;
+        let targets_size = (
+            ((screen_size.0 * resolution_scale) as u32).max(1),
+            ((screen_size.1 * resolution_scale) as u32).max(1),
         );
+        let targets = RenderTargets::new(ctx, targets_size.0, targets_size.1);
+        let jump_flood = JumpFlood::new(ctx, targets_size.0, targets_size.1);
         // offscreen pipeline
         // light (simple point light)
         let size = 1000.;
@@ -124,6 +243,43 @@ impl ShadowRenderer {
             images: vec![],
         };
 
+        // distance field shadows are traced on the same huge quad
+        let distance_field = DistanceField::new(
+            DISTANCE_FIELD_SIZE,
+            DISTANCE_FIELD_SIZE,
+            (vec2(-1., -1.), vec2(1., 1.)),
+        );
+        // distances are packed into bytes, so they can't be filtered
+        let distance_field_img = Texture::from_data_and_format(
+            ctx,
+            &distance_field.to_rgba8(),
+            TextureParams {
+                format: TextureFormat::RGBA8,
+                wrap: TextureWrap::Clamp,
+                filter: FilterMode::Nearest,
+                width: DISTANCE_FIELD_SIZE as u32,
+                height: DISTANCE_FIELD_SIZE as u32,
+            },
+        );
+        let distance_field_bindings = Bindings {
+            vertex_buffers: vec![vertex_light_buffer],
+            index_buffer: index_light_buffer,
+            images: vec![distance_field_img],
+        };
+        let distance_field_shader = Shader::new(
+            ctx,
+            offscreen_light_shader::VERTEX,
+            distance_field_shader::FRAGMENT,
+            distance_field_shader::META,
+        )
+        .unwrap();
+        let distance_field_pipeline = Pipeline::new(
+            ctx,
+            &[BufferLayout::default()],
+            &[VertexAttribute::new("pos", VertexFormat::Float2)],
+            distance_field_shader,
+        );
+
         let offscreen_light_shader = Shader::new(
             ctx,
             offscreen_light_shader::VERTEX,
@@ -138,21 +294,9 @@ impl ShadowRenderer {
             offscreen_light_shader,
         );
         // shadows
-        // Note that glitch when changing shadow segment
-        // Projective textures trick is not used for light (at least that way) in real life, but its good enough for this demo.
-        let (offscreen_pipeline, offscreen_bindings, vertex_buffer, index_buffer, offscreen_pass) = {
-            let offscreen_pass = RenderPass::new(ctx, color_img, depth_img);
-            let vertex_buffer =
-                Buffer::stream(ctx, BufferType::VertexBuffer, MAX_SHADOW_VERTICES_BYTES);
-            let index_buffer =
-                Buffer::stream(ctx, BufferType::IndexBuffer, MAX_SHADOW_INDICES_BYTES);
-
-            let offscreen_bindings = Bindings {
-                vertex_buffers: vec![vertex_buffer],
-                index_buffer,
-                images: vec![],
-            };
-
+        // Shadow volumes are extruded from every back facing edge, so there is no glitch when silhouette changes
+        // Penumbra wedges carry projective darkness coordinate, so it is constant along rays from the wedge apex
+        let offscreen_pipeline = {
             let offscreen_shader = Shader::new(
                 ctx,
                 offscreen_shader::VERTEX,
@@ -169,16 +313,10 @@ impl ShadowRenderer {
                 ],
                 offscreen_shader,
             );
-            (
-                offscreen_pipeline,
-                offscreen_bindings,
-                vertex_buffer,
-                index_buffer,
-                offscreen_pass,
-            )
+            offscreen_pipeline
         };
 
-        // display pipeline
+        // accumulate and display pipelines
         let size = 1.;
         // Screen size rectangle (without projection)
         #[rustfmt::skip]
@@ -192,10 +330,36 @@ impl ShadowRenderer {
 
         let indices: &[u16] = &[0, 1, 2, 3, 2, 0];
         let display_index_buffer = Buffer::immutable(ctx, BufferType::IndexBuffer, &indices);
+        // world coords are needed for lighting of sprites, so it's the huge light quad
+        let accumulate_bindings = Bindings {
+            vertex_buffers: vec![vertex_light_buffer],
+            index_buffer: index_light_buffer,
+            images: vec![targets.mask_img, targets.normal_img],
+        };
+        let accumulate_shader = Shader::new(
+            ctx,
+            accumulate_shader::VERTEX,
+            accumulate_shader::FRAGMENT,
+            accumulate_shader::META,
+        )
+        .unwrap();
+        // lights are added one by one
+        let accumulate_pipeline = Pipeline::with_params(
+            ctx,
+            &[BufferLayout::default()],
+            &[VertexAttribute::new("pos", VertexFormat::Float2)],
+            accumulate_shader,
+            PipelineParams {
+                depth_write: false,
+                color_blend: Some((Equation::Add, BlendFactor::One, BlendFactor::One)),
+                ..Default::default()
+            },
+        );
+
         let display_bindings = Bindings {
             vertex_buffers: vec![display_vertex_buffer],
             index_buffer: display_index_buffer,
-            images: vec![color_img],
+            images: vec![targets.light_img],
         };
         let default_shader = Shader::new(
             ctx,
@@ -204,6 +368,23 @@ impl ShadowRenderer {
             display_shader::META,
         )
         .unwrap();
+        // irradiance is added like the lights, its image is set before drawing
+        let irradiance_bindings = display_bindings.clone();
+        let irradiance_pipeline = Pipeline::with_params(
+            ctx,
+            &[BufferLayout::default()],
+            &[
+                VertexAttribute::new("pos", VertexFormat::Float2),
+                VertexAttribute::new("uv0", VertexFormat::Float2),
+            ],
+            default_shader,
+            PipelineParams {
+                depth_write: false,
+                color_blend: Some((Equation::Add, BlendFactor::One, BlendFactor::One)),
+                ..Default::default()
+            },
+        );
+        // scene color is multiplied by light
         let display_pipeline = Pipeline::with_params(
             ctx,
             &[BufferLayout::default()],
@@ -214,52 +395,188 @@ impl ShadowRenderer {
             default_shader,
             PipelineParams {
                 depth_write: false,
+                color_blend: Some((
+                    Equation::Add,
+                    BlendFactor::Zero,
+                    BlendFactor::Value(BlendValue::SourceColor),
+                )),
                 ..Default::default()
             },
         );
 
         ShadowRenderer {
             offscreen_pipeline,
+            accumulate_pipeline,
+            accumulate_bindings,
             display_pipeline,
+            irradiance_pipeline,
+            irradiance_bindings,
+            irradiance: None,
             offscreen_light_pipeline,
             offscreen_light_bindings,
-            offscreen_pass,
+            targets,
+            resolution_scale,
+            screen_size,
             display_bindings,
-            offscreen_bindings,
-            vertex_buffer,
-            index_buffer,
-            shadows: vec![],
+            batches: vec![],
+            shadow_ranges: vec![],
             vertices: vec![],
             indices: vec![],
-            shadow_smooth_th: 0.1,
-            light_pos: vec2(0., 0.),
-            light_size: 1f32,
+            shadow_smooth_th: 1.,
+            light_height: 1.,
+            sprite_height: 0.2,
+            mode: ShadowMode::Geometry,
+            distance_field,
+            distance_field_img,
+            distance_field_pipeline,
+            distance_field_bindings,
+            jump_flood,
+        }
+    }
+
+    /// Render shadow mask of every light, accumulate lights and multiply the screen by them
+    pub fn draw(&mut self, ctx: &mut Context, world: &World, projection: Mat4) {
+        let ambient = world.ambient;
+        ctx.begin_pass(
+            self.targets.light_pass,
+            PassAction::clear_color(ambient.x(), ambient.y(), ambient.z(), 1.),
+        );
+        ctx.end_render_pass();
+        for (i, light) in world.lights.iter().enumerate() {
+            self.offscreen_pass_draw(ctx, projection, i, light);
+            self.accumulate_pass_draw(ctx, projection, light);
+        }
+        if let Some(irradiance) = self.irradiance {
+            self.irradiance_pass_draw(ctx, irradiance);
         }
+        self.display_pass_draw(ctx);
     }
 
-    pub fn offscreen_pass_draw(&mut self, ctx: &mut Context, projection: Mat4) {
-        ctx.begin_pass(self.offscreen_pass, PassAction::default());
+    /// Render shadow mask of light into offscreen texture
+    /// Point and spot lights are rendered with 1/r^2 falloff, spot light is limited by cone
+    fn offscreen_pass_draw(
+        &mut self,
+        ctx: &mut Context,
+        projection: Mat4,
+        light_id: usize,
+        light: &Light,
+    ) {
+        ctx.begin_pass(self.targets.offscreen_pass, PassAction::default());
         // shadows
-        ctx.apply_pipeline(&self.offscreen_pipeline);
-        ctx.apply_bindings(&self.offscreen_bindings);
-        ctx.apply_uniforms(&offscreen_shader::Uniforms {
-            projection,
-            th: self.shadow_smooth_th,
-        });
-        ctx.draw(0, self.indices.len() as i32, 1);
+        match self.mode {
+            ShadowMode::Geometry => self.geometry_shadows_draw(ctx, projection, light_id),
+            ShadowMode::DistanceField => self.distance_field_shadows_draw(ctx, projection, light),
+        }
         // light
         ctx.apply_pipeline(&self.offscreen_light_pipeline);
         ctx.apply_bindings(&self.offscreen_light_bindings);
+        let (kind, direction, cone) = light_shader_kind(light);
         ctx.apply_uniforms(&offscreen_light_shader::Uniforms {
             projection,
-            light: self.light_pos,
-            size: self.light_size,
+            light: light.position,
+            size: light.radius * light.intensity,
+            kind,
+            direction,
+            cone,
+        });
+        ctx.draw(0, 6, 1);
+        ctx.end_render_pass();
+    }
+
+    /// Shadow volumes and penumbra wedges of the light
+    fn geometry_shadows_draw(&mut self, ctx: &mut Context, projection: Mat4, light_id: usize) {
+        if let Some((start, len)) = self.shadow_ranges.get(light_id).copied() {
+            ctx.apply_pipeline(&self.offscreen_pipeline);
+            let end = start + len;
+            let mut first = start;
+            // light's shadows may be split between batches
+            while first < end {
+                let batch = first / MAX_BATCH_VERTICES;
+                let batch_end = ((batch + 1) * MAX_BATCH_VERTICES).min(end);
+                ctx.apply_bindings(&self.batches[batch].bindings);
+                ctx.apply_uniforms(&offscreen_shader::Uniforms {
+                    projection,
+                    th: self.shadow_smooth_th,
+                });
+                ctx.draw(
+                    (first - batch * MAX_BATCH_VERTICES) as i32,
+                    (batch_end - first) as i32,
+                    1,
+                );
+                first = batch_end;
+            }
+        }
+    }
+
+    /// Darkness from sphere tracing the distance field, same as `DistanceField::soft_shadow`
+    fn distance_field_shadows_draw(&mut self, ctx: &mut Context, projection: Mat4, light: &Light) {
+        let field = &self.distance_field;
+        let texel = field.texel_size();
+        let (kind, direction, _) = light_shader_kind(light);
+        ctx.apply_pipeline(&self.distance_field_pipeline);
+        ctx.apply_bindings(&self.distance_field_bindings);
+        ctx.apply_uniforms(&distance_field_shader::Uniforms {
+            projection,
+            light: light.position,
+            kind,
+            direction,
+            softness: light.radius * self.shadow_smooth_th,
+            rect_min: field.rect.0,
+            rect_size: field.rect.1 - field.rect.0,
+            max_distance: field.max_distance(),
+            texel: texel.x().max(texel.y()),
+        });
+        ctx.draw(0, 6, 1);
+    }
+
+    /// Add light from shadow mask to the light buffer
+    /// Sprites from the normal buffer get Lambert term of the light
+    fn accumulate_pass_draw(&mut self, ctx: &mut Context, projection: Mat4, light: &Light) {
+        let (width, height) = self.targets_size();
+        let (kind, direction, _) = light_shader_kind(light);
+        ctx.begin_pass(self.targets.light_pass, PassAction::Nothing);
+        ctx.apply_pipeline(&self.accumulate_pipeline);
+        ctx.apply_bindings(&self.accumulate_bindings);
+        ctx.apply_uniforms(&accumulate_shader::Uniforms {
+            projection,
+            color: light.color * light.intensity,
+            size: vec2(width as f32, height as f32),
+            light: light.position,
+            kind,
+            direction,
+            light_height: self.light_height,
+            sprite_height: self.sprite_height,
         });
         ctx.draw(0, 6, 1);
         ctx.end_render_pass();
     }
 
-    pub fn draw(&mut self, ctx: &mut Context) {
+    /// Add irradiance to the light buffer
+    fn irradiance_pass_draw(&mut self, ctx: &mut Context, irradiance: Texture) {
+        self.irradiance_bindings.images[0] = irradiance;
+        ctx.begin_pass(self.targets.light_pass, PassAction::Nothing);
+        ctx.apply_pipeline(&self.irradiance_pipeline);
+        ctx.apply_bindings(&self.irradiance_bindings);
+        ctx.draw(0, 6, 1);
+        ctx.end_render_pass();
+    }
+
+    /// Pass of the normal buffer, sprites draw normals into it after `clear_normals`
+    pub fn normal_pass(&self) -> RenderPass {
+        self.targets.normal_pass
+    }
+
+    /// Forget normals of the previous frame, lighting of the ground is not changed by the normal buffer
+    pub fn clear_normals(&self, ctx: &mut Context) {
+        ctx.begin_pass(
+            self.targets.normal_pass,
+            PassAction::clear_color(0., 0., 0., 0.),
+        );
+        ctx.end_render_pass();
+    }
+
+    /// Modulate the screen by light buffer
+    fn display_pass_draw(&mut self, ctx: &mut Context) {
         ctx.begin_default_pass(PassAction::Nothing);
         ctx.apply_pipeline(&self.display_pipeline);
         ctx.apply_bindings(&self.display_bindings);
@@ -268,6 +585,23 @@ impl ShadowRenderer {
     }
 }
 
+/// Uniforms of light shader which describe kind of the light
+fn light_shader_kind(light: &Light) -> (f32, Vec2, Vec2) {
+    match light.kind {
+        LightKind::Point => (0., vec2(0., 0.), vec2(0., 0.)),
+        LightKind::Directional { direction } => (1., nonzero_direction(direction), vec2(0., 0.)),
+        LightKind::Spot {
+            direction,
+            inner_angle,
+            outer_angle,
+        } => (
+            2.,
+            nonzero_direction(direction).normalize(),
+            vec2(outer_angle.cos(), inner_angle.cos()),
+        ),
+    }
+}
+
 /// Vertex and Fragment shader to render light (used in offscreen pipeline)
 mod offscreen_light_shader {
     use glam::Vec2;
@@ -284,15 +618,28 @@ mod offscreen_light_shader {
         fpos = pos;
     }"#;
 
+    // kind: 0 -- point, 1 -- directional, 2 -- spot
+    // cone: (cos(outer_angle), cos(inner_angle)) of spot light
     pub const FRAGMENT: &str = r#"#version 100
-    uniform lowp vec2 light;
-    uniform lowp float size;
+    uniform highp vec2 light;
+    uniform highp float size;
+    uniform lowp float kind;
+    uniform highp vec2 direction;
+    uniform mediump vec2 cone;
 
     varying highp vec2 fpos;
 
     void main() {
-        lowp vec2 dir = fpos - light;
-        lowp float dst = size / (dir.x * dir.x + dir.y * dir.y);
+        if (kind > 0.5 && kind < 1.5) {
+            // sun has no falloff
+            gl_FragColor = vec4(1., 1., 1., 0.);
+            return;
+        }
+        highp vec2 dir = fpos - light;
+        highp float dst = size / (dir.x * dir.x + dir.y * dir.y);
+        if (kind > 1.5) {
+            dst *= smoothstep(cone.x, cone.y, dot(normalize(dir), direction));
+        }
         gl_FragColor = vec4(1., 1., 1., 1. - dst);
     }"#;
 
@@ -303,6 +650,9 @@ mod offscreen_light_shader {
                 UniformDesc::new("projection", UniformType::Mat4),
                 UniformDesc::new("light", UniformType::Float2),
                 UniformDesc::new("size", UniformType::Float1),
+                UniformDesc::new("kind", UniformType::Float1),
+                UniformDesc::new("direction", UniformType::Float2),
+                UniformDesc::new("cone", UniformType::Float2),
             ],
         },
     };
@@ -312,6 +662,99 @@ mod offscreen_light_shader {
         pub projection: glam::Mat4,
         pub light: Vec2,
         pub size: f32,
+        pub kind: f32,
+        pub direction: Vec2,
+        pub cone: Vec2,
+    }
+}
+
+/// Fragment shader of distance field shadows (used in offscreen pipeline with offscreen_light_shader::VERTEX)
+mod distance_field_shader {
+    use glam::Vec2;
+    use miniquad::*;
+
+    // distance is packed into red and green channels, see DistanceField::to_rgba8
+    // MAX_STEPS of DistanceField is the loop limit
+    pub const FRAGMENT: &str = r#"#version 100
+    uniform sampler2D sdf;
+    uniform highp vec2 light;
+    uniform highp float kind;
+    uniform highp vec2 direction;
+    uniform highp float softness;
+    uniform highp vec2 rect_min;
+    uniform highp vec2 rect_size;
+    uniform highp float max_distance;
+    uniform highp float texel;
+
+    varying highp vec2 fpos;
+
+    highp float sample_sdf(highp vec2 p) {
+        highp vec2 uv = clamp((p - rect_min) / rect_size, 0., 1.);
+        highp vec2 rg = floor(texture2D(sdf, uv).rg * 255. + 0.5);
+        return ((rg.x * 256. + rg.y) / 65535. - 0.5) * 2. * max_distance;
+    }
+
+    void main() {
+        highp vec2 dir;
+        highp float len;
+        if (kind > 0.5 && kind < 1.5) {
+            // sun
+            dir = -normalize(direction);
+            len = max_distance;
+        } else {
+            highp vec2 to_light = light - fpos;
+            len = length(to_light);
+            dir = to_light / max(len, 0.000001);
+        }
+        highp float visibility = 1.;
+        if (sample_sdf(fpos) < 0.) {
+            visibility = 0.;
+        }
+        highp float k = len / max(softness, 0.001);
+        highp float t = 2. * texel;
+        for (int i = 0; i < 64; i++) {
+            if (t >= len || visibility <= 0.) {
+                break;
+            }
+            highp float h = sample_sdf(fpos + dir * t);
+            if (h < 0.) {
+                visibility = 0.;
+                break;
+            }
+            visibility = min(visibility, k * h / t);
+            t += max(h, 0.5 * texel);
+        }
+        gl_FragColor = vec4(1., 1., 1., 1. - clamp(visibility, 0., 1.));
+    }"#;
+
+    pub const META: ShaderMeta = ShaderMeta {
+        images: &["sdf"],
+        uniforms: UniformBlockLayout {
+            uniforms: &[
+                UniformDesc::new("projection", UniformType::Mat4),
+                UniformDesc::new("light", UniformType::Float2),
+                UniformDesc::new("kind", UniformType::Float1),
+                UniformDesc::new("direction", UniformType::Float2),
+                UniformDesc::new("softness", UniformType::Float1),
+                UniformDesc::new("rect_min", UniformType::Float2),
+                UniformDesc::new("rect_size", UniformType::Float2),
+                UniformDesc::new("max_distance", UniformType::Float1),
+                UniformDesc::new("texel", UniformType::Float1),
+            ],
+        },
+    };
+
+    #[repr(C)]
+    pub struct Uniforms {
+        pub projection: glam::Mat4,
+        pub light: Vec2,
+        pub kind: f32,
+        pub direction: Vec2,
+        pub softness: f32,
+        pub rect_min: Vec2,
+        pub rect_size: Vec2,
+        pub max_distance: f32,
+        pub texel: f32,
     }
 }
 
@@ -337,13 +780,11 @@ mod offscreen_shader {
     uniform highp float th;
 
     void main() {
-        lowp float mid = 0.5 - abs(inter_normal.x / inter_normal.z - 0.5);
-        if (mid < th) {
-            mid = (th - mid) / th;
-        } else {
-            mid = 0.;
-        }
-        gl_FragColor = vec4(1., 1., 1., 1. - mid);
+        // 0 -- lit, 1 -- umbra, penumbra wedges are in between
+        highp float shadow = clamp(inter_normal.x / inter_normal.z, 0., 1.);
+        // th = 1 -- physical penumbra, smaller th makes border sharper
+        shadow = clamp((shadow - 0.5) / max(th, 0.01) + 0.5, 0., 1.);
+        gl_FragColor = vec4(1., 1., 1., shadow);
     }"#;
 
     pub const META: ShaderMeta = ShaderMeta {
@@ -363,8 +804,87 @@ mod offscreen_shader {
     }
 }
 
+/// Vertex and Fragment shader to add light from shadow mask into light buffer
+/// Pixels of normal mapped sprites are lit by Lambert term, see normal_map::lambert
+mod accumulate_shader {
+    use glam::Vec2;
+    use miniquad::*;
+
+    pub const VERTEX: &str = r#"#version 100
+    attribute vec2 pos;
+    varying highp vec2 fpos;
+
+    uniform mat4 projection;
+
+    void main() {
+        gl_Position = projection * vec4(pos, 0, 1);
+        fpos = pos;
+    }"#;
+
+    // kind: 0 -- point, 1 -- directional, 2 -- spot
+    pub const FRAGMENT: &str = r#"#version 100
+    varying highp vec2 fpos;
+
+    uniform sampler2D tex;
+    uniform sampler2D normals;
+    uniform lowp vec3 color;
+    uniform highp vec2 size;
+    uniform highp vec2 light;
+    uniform highp float kind;
+    uniform highp vec2 direction;
+    uniform highp float light_height;
+    uniform highp float sprite_height;
+
+    void main() {
+        highp vec2 uv = gl_FragCoord.xy / size;
+        // red channel of the mask is the darkness
+        lowp float lit = 1. - texture2D(tex, uv).r;
+        highp vec4 sprite = texture2D(normals, uv);
+        if (sprite.a > 0.5) {
+            highp vec2 xy = sprite.xy * 2. - 1.;
+            highp vec3 normal = vec3(xy, sqrt(max(1. - dot(xy, xy), 0.)));
+            highp vec3 to_light;
+            if (kind > 0.5 && kind < 1.5) {
+                to_light = vec3(-normalize(direction), light_height);
+            } else {
+                to_light = vec3(light - fpos, light_height - sprite.z * sprite_height);
+            }
+            lit *= max(dot(normalize(normal), normalize(to_light)), 0.);
+        }
+        gl_FragColor = vec4(color * lit, 0.);
+    }"#;
+
+    pub const META: ShaderMeta = ShaderMeta {
+        images: &["tex", "normals"],
+        uniforms: UniformBlockLayout {
+            uniforms: &[
+                UniformDesc::new("projection", UniformType::Mat4),
+                UniformDesc::new("color", UniformType::Float3),
+                UniformDesc::new("size", UniformType::Float2),
+                UniformDesc::new("light", UniformType::Float2),
+                UniformDesc::new("kind", UniformType::Float1),
+                UniformDesc::new("direction", UniformType::Float2),
+                UniformDesc::new("light_height", UniformType::Float1),
+                UniformDesc::new("sprite_height", UniformType::Float1),
+            ],
+        },
+    };
+
+    #[repr(C)]
+    pub struct Uniforms {
+        pub projection: glam::Mat4,
+        pub color: glam::Vec3,
+        pub size: Vec2,
+        pub light: Vec2,
+        pub kind: f32,
+        pub direction: Vec2,
+        pub light_height: f32,
+        pub sprite_height: f32,
+    }
+}
+
 /// Vertex and Fragment shader to render texture fullscreen
-/// (texture comes from offscreen pipeline)
+/// (texture is the light buffer)
 mod display_shader {
     use miniquad::*;
 
@@ -375,8 +895,6 @@ mod display_shader {
     varying lowp vec2 uv;
     varying lowp vec2 fpos;
 
-    // uniform mat4 projection;
-
     void main() {
         gl_Position = vec4(pos, 0, 1);
         uv = uv0;
@@ -390,8 +908,7 @@ mod display_shader {
     uniform sampler2D tex;
 
     void main() {
-        lowp vec4 color = texture2D(tex, uv);
-        gl_FragColor = vec4(vec3(1., 1., 0.5) - color.rgb, color.r);
+        gl_FragColor = vec4(texture2D(tex, uv).rgb, 1.);
     }"#;
 
     pub const META: ShaderMeta = ShaderMeta {
@@ -0,0 +1,377 @@
+use crate::geometry::{nonzero_direction, ShadowPoint};
+use crate::light::{Light, LightKind};
+use crate::world::World;
+use glam::{vec2, vec3, vec4, Mat4, Vec2, Vec3};
+use std::{fs::File, io, io::BufWriter, path::Path};
+
+/// CPU version of the shadow passes of `ShadowRenderer`, renders the same geometry
+/// and mirrors its shaders, so the output can be tested without GPU
+/// Buffers are stored like GL textures: the first row is the bottom one
+pub struct SoftwareRenderer {
+    pub width: usize,
+    pub height: usize,
+    /// Same as `ShadowRenderer::shadow_smooth_th`
+    pub shadow_smooth_th: f32,
+}
+
+impl SoftwareRenderer {
+    pub fn new(width: usize, height: usize) -> SoftwareRenderer {
+        SoftwareRenderer {
+            width,
+            height,
+            shadow_smooth_th: 1.,
+        }
+    }
+
+    /// Darkness of light's shadow mask (red channel of the offscreen texture)
+    pub fn shadow_mask(&self, world: &World, light_id: usize, projection: Mat4) -> Vec<f32> {
+        self.mask_of(world.shadows(light_id), &world.lights[light_id], projection)
+    }
+
+    /// Darkness of the mask rendered from shadow geometry and light
+    pub fn mask_of(&self, shadows: &[ShadowPoint], light: &Light, projection: Mat4) -> Vec<f32> {
+        let mut mask = vec![0.; self.width * self.height];
+        for triangle in shadows.chunks_exact(3) {
+            self.rasterize_shadow(&mut mask, triangle, projection);
+        }
+        // light quad covers the whole screen, alpha is darkness
+        let inverse = projection.inverse();
+        for (i, darkness) in mask.iter_mut().enumerate() {
+            let ndc = self.pixel_ndc(i % self.width, i / self.width);
+            let pos = inverse * vec4(ndc.x(), ndc.y(), 0., 1.);
+            let alpha = clamp01(1. - light_falloff(light, vec2(pos.x(), pos.y())));
+            *darkness = alpha + (1. - alpha) * *darkness;
+        }
+        mask
+    }
+
+    /// Light buffer: ambient plus every light's color where its mask is lit
+    pub fn light_buffer(&self, world: &World, projection: Mat4) -> Vec<Vec3> {
+        let mut buffer = vec![world.ambient; self.width * self.height];
+        for (i, light) in world.lights.iter().enumerate() {
+            let mask = self.shadow_mask(world, i, projection);
+            let color = light.color * light.intensity;
+            for (pixel, darkness) in buffer.iter_mut().zip(mask.iter()) {
+                *pixel += color * (1. - darkness);
+            }
+        }
+        // the light buffer is RGBA8, so it saturates
+        for pixel in buffer.iter_mut() {
+            *pixel = vec3(clamp01(pixel.x()), clamp01(pixel.y()), clamp01(pixel.z()));
+        }
+        buffer
+    }
+
+    /// Center of the pixel in normalized device coords
+    fn pixel_ndc(&self, x: usize, y: usize) -> Vec2 {
+        vec2(
+            (x as f32 + 0.5) / self.width as f32 * 2. - 1.,
+            (y as f32 + 0.5) / self.height as f32 * 2. - 1.,
+        )
+    }
+
+    /// Blend shadow triangle into the mask like the offscreen shader does
+    fn rasterize_shadow(&self, mask: &mut [f32], triangle: &[ShadowPoint], projection: Mat4) {
+        let screen: Vec<Vec2> = triangle
+            .iter()
+            .map(|p| {
+                let clip = projection * vec4(p.pos.x(), p.pos.y(), 0., 1.);
+                vec2(
+                    (clip.x() / clip.w() + 1.) / 2. * self.width as f32,
+                    (clip.y() / clip.w() + 1.) / 2. * self.height as f32,
+                )
+            })
+            .collect();
+        let mut order = [0, 1, 2];
+        let mut area = edge(screen[0], screen[1], screen[2]);
+        if area == 0. {
+            return;
+        }
+        if area < 0. {
+            order.swap(1, 2);
+            area = -area;
+        }
+        let [a, b, c] = [screen[order[0]], screen[order[1]], screen[order[2]]];
+        let normals = [
+            triangle[order[0]].normal,
+            triangle[order[1]].normal,
+            triangle[order[2]].normal,
+        ];
+        let (min, max) = (a.min(b).min(c), a.max(b).max(c));
+        let x_range = pixel_range(min.x(), max.x(), self.width);
+        let y_range = pixel_range(min.y(), max.y(), self.height);
+        let th = self.shadow_smooth_th.max(0.01);
+        for y in y_range {
+            for x in x_range.clone() {
+                let p = vec2(x as f32 + 0.5, y as f32 + 0.5);
+                let weights = [edge(b, c, p), edge(c, a, p), edge(a, b, p)];
+                let covered = weights
+                    .iter()
+                    .zip([(b, c), (c, a), (a, b)].iter())
+                    .all(|(w, (from, to))| *w > 0. || (*w == 0. && is_top_left(*from, *to)));
+                if !covered {
+                    continue;
+                }
+                let normal =
+                    (normals[0] * weights[0] + normals[1] * weights[1] + normals[2] * weights[2])
+                        / area;
+                let shadow = clamp01(normal.x() / normal.z());
+                let shadow = clamp01((shadow - 0.5) / th + 0.5);
+                let darkness = &mut mask[y * self.width + x];
+                *darkness = shadow + (1. - shadow) * *darkness;
+            }
+        }
+    }
+}
+
+/// Brightness of the light at the point, same as offscreen_light_shader
+fn light_falloff(light: &Light, pos: Vec2) -> f32 {
+    let dir = pos - light.position;
+    let size = light.radius * light.intensity;
+    match light.kind {
+        // sun has no falloff
+        LightKind::Directional { .. } => 1.,
+        LightKind::Point => size / dir.length_squared(),
+        LightKind::Spot {
+            direction,
+            inner_angle,
+            outer_angle,
+        } => {
+            let cos = dir
+                .normalize()
+                .dot(nonzero_direction(direction).normalize());
+            size / dir.length_squared() * smoothstep(outer_angle.cos(), inner_angle.cos(), cos)
+        }
+    }
+}
+
+/// Doubled signed area of (a, b, p), positive if p is to the left of ab
+fn edge(a: Vec2, b: Vec2, p: Vec2) -> f32 {
+    (b.x() - a.x()) * (p.y() - a.y()) - (b.y() - a.y()) * (p.x() - a.x())
+}
+
+/// Top-left fill rule (for counter clockwise triangle with y up),
+/// pixels on the edge shared by two triangles are drawn only once
+fn is_top_left(from: Vec2, to: Vec2) -> bool {
+    let (dx, dy) = (to.x() - from.x(), to.y() - from.y());
+    (dy == 0. && dx < 0.) || dy < 0.
+}
+
+/// Pixels which centers may be inside [min, max]
+fn pixel_range(min: f32, max: f32, size: usize) -> std::ops::Range<usize> {
+    let first = (min - 0.5).ceil().max(0.) as usize;
+    let last = ((max - 0.5).floor() + 1.).max(0.).min(size as f32) as usize;
+    first..last.max(first)
+}
+
+fn clamp01(v: f32) -> f32 {
+    // NaN (0 / 0) becomes 0 like in shaders
+    if v.is_nan() {
+        0.
+    } else {
+        v.clamp(0., 1.)
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = clamp01((x - edge0) / (edge1 - edge0));
+    t * t * (3. - 2. * t)
+}
+
+/// RGBA8 image, the first row is the top one (like in png)
+#[derive(Clone, Debug, PartialEq)]
+pub struct RgbaImage {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+}
+
+impl RgbaImage {
+    /// Grayscale image of darkness mask
+    pub fn from_mask(width: usize, height: usize, mask: &[f32]) -> RgbaImage {
+        let pixels: Vec<_> = mask.iter().map(|d| vec3(*d, *d, *d)).collect();
+        RgbaImage::from_light_buffer(width, height, &pixels)
+    }
+
+    pub fn from_light_buffer(width: usize, height: usize, buffer: &[Vec3]) -> RgbaImage {
+        let mut data = Vec::with_capacity(width * height * 4);
+        // buffers start from the bottom row
+        for row in buffer.chunks_exact(width).rev() {
+            for pixel in row {
+                data.extend_from_slice(&[
+                    to_u8(pixel.x()),
+                    to_u8(pixel.y()),
+                    to_u8(pixel.z()),
+                    255,
+                ]);
+            }
+        }
+        RgbaImage {
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// Max difference of channels, images must be the same size
+    pub fn max_difference(&self, other: &RgbaImage) -> u8 {
+        assert_eq!((self.width, self.height), (other.width, other.height));
+        self.data
+            .iter()
+            .zip(other.data.iter())
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn read_png<P: AsRef<Path>>(path: P) -> io::Result<RgbaImage> {
+        let decoder = png::Decoder::new(File::open(path)?);
+        let (info, mut reader) = decoder.read_info().map_err(png_error)?;
+        if info.color_type != png::ColorType::RGBA || info.bit_depth != png::BitDepth::Eight {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "png is not RGBA8",
+            ));
+        }
+        let mut data = vec![0; info.buffer_size()];
+        reader.next_frame(&mut data).map_err(png_error)?;
+        Ok(RgbaImage {
+            width: info.width as usize,
+            height: info.height as usize,
+            data,
+        })
+    }
+
+    pub fn write_png<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(png_error)?;
+        writer.write_image_data(&self.data).map_err(png_error)
+    }
+}
+
+fn to_u8(v: f32) -> u8 {
+    (clamp01(v) * 255. + 0.5) as u8
+}
+
+fn png_error<E: std::error::Error + Send + Sync + 'static>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// Compare image with png in tests/golden, set UPDATE_GOLDEN=1 to overwrite it instead
+#[cfg(test)]
+fn check_golden(name: &str, image: &RgbaImage) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.png", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        image.write_png(&path).unwrap();
+        return;
+    }
+    let golden = RgbaImage::read_png(&path).unwrap();
+    // allow rounding differences between platforms
+    assert!(
+        image.max_difference(&golden) <= 2,
+        "{} differs from golden",
+        name
+    );
+}
+
+#[cfg(test)]
+fn square_world(light: Light) -> World {
+    use crate::transform::Transform2D;
+    use crate::world::Occluder;
+    use geo::Polygon;
+    let mut world = World::default();
+    world.occluders.push(Occluder {
+        polygon: Polygon::new(
+            vec![(-0.2, -0.2), (0.2, -0.2), (0.2, 0.2), (-0.2, 0.2)].into(),
+            vec![],
+        ),
+        transform: Transform2D::from_translation(vec2(0.4, 0.)),
+        velocity: vec2(0., 0.),
+        angular_velocity: 0.,
+        fixed: true,
+        emission: Vec3::zero(),
+    });
+    world.lights.push(light);
+    world.compute_shadows();
+    world
+}
+
+#[cfg(test)]
+fn test_projection(half_size: f32) -> Mat4 {
+    let mut camera = crate::camera::Camera::new(64., 64.);
+    camera.zoom = 0.5 / half_size;
+    camera.get_projection()
+}
+
+#[test]
+fn shared_edges_are_drawn_once() {
+    let renderer = SoftwareRenderer::new(16, 16);
+    let point = |x, y| ShadowPoint {
+        pos: vec2(x, y),
+        normal: vec3(0.5, 0., 1.),
+    };
+    // half dark square of two triangles covering the screen
+    let shadows = [
+        point(-1., -1.),
+        point(1., -1.),
+        point(1., 1.),
+        point(1., 1.),
+        point(-1., 1.),
+        point(-1., -1.),
+    ];
+    let light = Light::directional(vec2(1., 0.));
+    let mask = renderer.mask_of(&shadows, &light, Mat4::identity());
+    for darkness in mask {
+        assert!((darkness - 0.5).abs() < 1E-6);
+    }
+}
+
+#[test]
+fn threshold_sharpens_penumbra() {
+    // bright light, so the mask has no falloff gradient
+    let mut light = Light::new(vec2(-1., 0.), 0.3);
+    light.intensity = 100.;
+    let world = square_world(light);
+    let projection = test_projection(2.);
+    let mut renderer = SoftwareRenderer::new(64, 64);
+    let soft = renderer.shadow_mask(&world, 0, projection);
+    renderer.shadow_smooth_th = 0.1;
+    let sharp = renderer.shadow_mask(&world, 0, projection);
+    let gray = |mask: &[f32]| mask.iter().filter(|d| **d > 0.05 && **d < 0.95).count();
+    assert!(gray(&sharp) < gray(&soft));
+}
+
+#[test]
+fn golden_point_light_mask() {
+    let world = square_world(Light::new(vec2(-1., 0.), 0.3));
+    let renderer = SoftwareRenderer::new(64, 64);
+    let mask = renderer.shadow_mask(&world, 0, test_projection(2.));
+    check_golden("point_light_mask", &RgbaImage::from_mask(64, 64, &mask));
+}
+
+#[test]
+fn golden_spot_light_mask() {
+    let light = Light::spot(vec2(-1., 0.5), 0.2, vec2(1., -0.5), 0.3, 0.6);
+    let world = square_world(light);
+    let mut renderer = SoftwareRenderer::new(64, 64);
+    renderer.shadow_smooth_th = 0.5;
+    let mask = renderer.shadow_mask(&world, 0, test_projection(2.));
+    check_golden("spot_light_mask", &RgbaImage::from_mask(64, 64, &mask));
+}
+
+#[test]
+fn golden_default_scene() {
+    let mut world = World::from_scene(&crate::scene::SceneDesc::default_scene());
+    world.compute_shadows();
+    let renderer = SoftwareRenderer::new(64, 64);
+    let buffer = renderer.light_buffer(&world, test_projection(5.));
+    check_golden(
+        "default_scene_light",
+        &RgbaImage::from_light_buffer(64, 64, &buffer),
+    );
+}
@@ -0,0 +1,50 @@
+use miniquad::*;
+
+/// Initial size of buffers in bytes
+const INITIAL_CAPACITY: usize = 1024;
+
+/// Vertex and index stream buffers with bindings.
+/// Buffers are reallocated with bigger size when data doesn't fit.
+pub struct StreamBuffers {
+    pub bindings: Bindings,
+    /// Capacity of vertex buffer in bytes
+    vertex_capacity: usize,
+    /// Capacity of index buffer in bytes
+    index_capacity: usize,
+}
+
+impl StreamBuffers {
+    pub fn new(ctx: &mut Context, images: Vec<Texture>) -> StreamBuffers {
+        let vertex_buffer = Buffer::stream(ctx, BufferType::VertexBuffer, INITIAL_CAPACITY);
+        let index_buffer = Buffer::stream(ctx, BufferType::IndexBuffer, INITIAL_CAPACITY);
+        StreamBuffers {
+            bindings: Bindings {
+                vertex_buffers: vec![vertex_buffer],
+                index_buffer,
+                images,
+            },
+            vertex_capacity: INITIAL_CAPACITY,
+            index_capacity: INITIAL_CAPACITY,
+        }
+    }
+
+    /// Upload new geometry, grow buffers if needed
+    pub fn update<T>(&mut self, ctx: &mut Context, vertices: &[T], indices: &[u16]) {
+        let vertices_size = vertices.len() * std::mem::size_of::<T>();
+        if vertices_size > self.vertex_capacity {
+            self.vertex_capacity = vertices_size.next_power_of_two();
+            self.bindings.vertex_buffers[0].delete();
+            self.bindings.vertex_buffers[0] =
+                Buffer::stream(ctx, BufferType::VertexBuffer, self.vertex_capacity);
+        }
+        let indices_size = indices.len() * std::mem::size_of::<u16>();
+        if indices_size > self.index_capacity {
+            self.index_capacity = indices_size.next_power_of_two();
+            self.bindings.index_buffer.delete();
+            self.bindings.index_buffer =
+                Buffer::stream(ctx, BufferType::IndexBuffer, self.index_capacity);
+        }
+        self.bindings.vertex_buffers[0].update(ctx, vertices);
+        self.bindings.index_buffer.update(ctx, indices);
+    }
+}
@@ -0,0 +1,262 @@
+use crate::distance_field::{occluders_distance, DistanceField};
+use crate::geometry::polygon_segments;
+use crate::sdf::{scene_emission, SdfShape};
+use crate::world::World;
+use glam::{vec2, Vec2, Vec3};
+use std::f32::consts::PI;
+
+/// Max number of rays per pixel, the loop limit of the GI shader
+pub const MAX_RAYS: usize = 16;
+
+/// Max number of sphere tracing steps of one ray, same as in the GI shader
+pub const MAX_STEPS: usize = 32;
+
+/// Smallest weight of the new frame in the temporal accumulation, older frames fade out with it
+pub const MIN_HISTORY_WEIGHT: f32 = 0.05;
+
+/// Parameters of irradiance gathering, same for the CPU reference and the GI shader
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GiParams {
+    /// Rays per pixel in one frame, up to `MAX_RAYS`
+    pub rays: usize,
+    /// Part of the previous irradiance in front of a hit surface which is reflected, 0 -- no bounces
+    pub bounce: f32,
+    /// Multiplier of the gathered light
+    pub intensity: f32,
+}
+
+impl Default for GiParams {
+    fn default() -> GiParams {
+        GiParams {
+            rays: 8,
+            bounce: 0.3,
+            intensity: 2.,
+        }
+    }
+}
+
+/// Distance field of occluders and SDF shapes with the light emitted by the nearest surface
+pub struct RadianceField {
+    pub distance: DistanceField,
+    /// Emission of the nearest surface at texel centers, same layout as distances
+    pub emission: Vec<Vec3>,
+}
+
+impl RadianceField {
+    /// Field without surfaces
+    pub fn new(width: usize, height: usize, rect: (Vec2, Vec2)) -> RadianceField {
+        RadianceField {
+            distance: DistanceField::new(width, height, rect),
+            emission: vec![Vec3::zero(); width * height],
+        }
+    }
+
+    /// Recompute distances and emission of occluders and shapes over the world bounds
+    pub fn bake(&mut self, world: &World, shapes: &[SdfShape]) {
+        let field = &mut self.distance;
+        field.rect = world.bounds;
+        let far = field.max_distance();
+        let occluders: Vec<_> = world
+            .occluders
+            .iter()
+            .map(|occluder| polygon_segments(&occluder.placed_polygon()))
+            .collect();
+        for y in 0..field.height {
+            for x in 0..field.width {
+                let p = field.texel_center(x, y);
+                let (mut distance, nearest) = occluders_distance(&occluders, p, far);
+                let mut emission = nearest.map_or(Vec3::zero(), |i| world.occluders[i].emission);
+                if !shapes.is_empty() {
+                    let (shape_distance, shape_emission) = scene_emission(shapes, p);
+                    if shape_distance < distance {
+                        distance = shape_distance;
+                        emission = shape_emission;
+                    }
+                }
+                field.data[y * field.width + x] = distance;
+                self.emission[y * field.width + x] = emission;
+            }
+        }
+    }
+
+    /// Distance and emission at the nearest texel
+    pub fn sample(&self, point: Vec2) -> (f32, Vec3) {
+        let i = self.distance.texel_index(point);
+        (self.distance.data[i], self.emission[i])
+    }
+
+    /// Emission in RGB bytes, it is clamped to [0, 1]
+    pub fn emission_rgba8(&self) -> Vec<u8> {
+        let byte = |v: f32| (v.clamp(0., 1.) * 255.).round() as u8;
+        let mut bytes = Vec::with_capacity(self.emission.len() * 4);
+        for e in self.emission.iter() {
+            bytes.extend_from_slice(&[byte(e.x()), byte(e.y()), byte(e.z()), 255]);
+        }
+        bytes
+    }
+
+    /// Light gathered at the point by sphere tracing rays in all directions, mirrors the GI shader
+    /// Rays are rotated by `offset` ray spacings, a hit adds the surface emission and `bounce` part of
+    /// `previous` irradiance right in front of the surface. Points inside surfaces get their own emission
+    pub fn irradiance(
+        &self,
+        point: Vec2,
+        params: &GiParams,
+        offset: f32,
+        previous: impl Fn(Vec2) -> Vec3,
+    ) -> Vec3 {
+        let (distance, emission) = self.sample(point);
+        if distance < 0. {
+            return emission * params.intensity;
+        }
+        let texel_size = self.distance.texel_size();
+        let texel = texel_size.x().max(texel_size.y());
+        let max_distance = self.distance.max_distance();
+        let rays = params.rays.clamp(1, MAX_RAYS);
+        let mut light = Vec3::zero();
+        for i in 0..rays {
+            let angle = (i as f32 + offset) * 2. * PI / rays as f32;
+            let dir = vec2(angle.cos(), angle.sin());
+            let mut t = texel;
+            for _ in 0..MAX_STEPS {
+                let p = point + dir * t;
+                let (h, emission) = self.sample(p);
+                if h < 0.5 * texel {
+                    light += emission + previous(p - dir * 2. * texel) * params.bounce;
+                    break;
+                }
+                t += h;
+                if t > max_distance {
+                    break;
+                }
+            }
+        }
+        light / rays as f32 * params.intensity
+    }
+}
+
+/// Rotation of rays in the frame (in ray spacings), so accumulated frames cover new directions
+pub fn frame_offset(frame: usize) -> f32 {
+    // golden ratio sequence spreads the offsets evenly
+    (frame as f32 * 0.618_034).fract()
+}
+
+/// Weight of the new frame mixed into the accumulated irradiance
+/// The first frames are averaged, later ones fade out, so moving surfaces don't leave trails forever
+pub fn history_weight(frame: usize) -> f32 {
+    (1. / (frame + 1) as f32).max(MIN_HISTORY_WEIGHT)
+}
+
+#[cfg(test)]
+fn lamp_world(emission: Vec3) -> World {
+    use crate::scene::OccluderDesc;
+    use crate::world::Occluder;
+    let square = |position: [f32; 2], half: f32, emission: Vec3| {
+        Occluder::from(&OccluderDesc {
+            exterior: vec![[-half, -half], [half, -half], [half, half], [-half, half]],
+            holes: vec![],
+            position,
+            angle: 0.,
+            scale: [1., 1.],
+            velocity: [0., 0.],
+            angular_velocity: 0.,
+            fixed: true,
+            emission: [emission.x(), emission.y(), emission.z()],
+        })
+    };
+    let mut world = World::default();
+    world.bounds = (vec2(-2., -2.), vec2(2., 2.));
+    // lamp in the middle and a blocker right of it
+    world.occluders.push(square([0., 0.], 0.2, emission));
+    world.occluders.push(square([1., 0.], 0.2, Vec3::zero()));
+    world
+}
+
+#[cfg(test)]
+fn baked(world: &World, shapes: &[SdfShape]) -> RadianceField {
+    let mut field = RadianceField::new(128, 128, world.bounds);
+    field.bake(world, shapes);
+    field
+}
+
+#[cfg(test)]
+fn gathered(field: &RadianceField, point: Vec2, previous: Vec3) -> Vec3 {
+    let params = GiParams {
+        rays: MAX_RAYS,
+        ..GiParams::default()
+    };
+    field.irradiance(point, &params, 0., |_| previous)
+}
+
+#[test]
+fn baked_emission() {
+    use crate::sdf::SdfPrimitive;
+    use crate::transform::Transform2D;
+    use glam::vec3;
+    let orange = vec3(1., 0.5, 0.);
+    let shape = SdfShape {
+        primitive: SdfPrimitive::Circle { radius: 0.2 },
+        transform: Transform2D::from_translation(vec2(0., 1.)),
+        color: vec3(0., 0., 1.),
+        emission: 0.5,
+        ..SdfShape::default()
+    };
+    let field = baked(&lamp_world(orange), &[shape]);
+    assert_eq!(field.sample(vec2(0., 0.)).1, orange);
+    assert!(field.sample(vec2(0., 0.)).0 < 0.);
+    // emission of the nearest surface, shapes are blockers too
+    assert_eq!(field.sample(vec2(0., 0.3)).1, orange);
+    assert_eq!(field.sample(vec2(0., 1.)).1, vec3(0., 0., 0.5));
+    assert!(field.sample(vec2(0., 1.)).0 < 0.);
+    assert_eq!(field.sample(vec2(1., 0.)).1, Vec3::zero());
+    let bytes = field.emission_rgba8();
+    let i = field.distance.texel_index(vec2(0., 0.));
+    assert_eq!(&bytes[4 * i..4 * i + 4], &[255, 128, 0, 255]);
+}
+
+#[test]
+fn emitted_light() {
+    let orange = glam::vec3(1., 0.5, 0.);
+    let field = baked(&lamp_world(orange), &[]);
+    let near = gathered(&field, vec2(0., 0.5), Vec3::zero());
+    let far = gathered(&field, vec2(0., 1.5), Vec3::zero());
+    assert!(near.x() > far.x() && far.x() > 0.);
+    // color of the emitter is kept
+    assert!((near.y() - near.x() * 0.5).abs() < 1E-5 && near.z() == 0.);
+    // the blocker casts a shadow
+    let behind = gathered(&field, vec2(1.5, 0.), Vec3::zero());
+    let aside = gathered(&field, vec2(0., 1.5), Vec3::zero());
+    assert!(behind.x() < aside.x());
+    // emitters glow
+    assert_eq!(gathered(&field, vec2(0., 0.), Vec3::zero()), orange * 2.);
+    // no emitters -- no light
+    let dark = baked(&lamp_world(Vec3::zero()), &[]);
+    assert_eq!(gathered(&dark, vec2(0., 1.), Vec3::zero()), Vec3::zero());
+}
+
+#[test]
+fn bounced_light() {
+    let dark = baked(&lamp_world(Vec3::zero()), &[]);
+    let lit = glam::vec3(0.5, 0.5, 0.5);
+    // surfaces reflect light which was around them in the previous frame
+    let bounced = gathered(&dark, vec2(0.6, 0.), lit);
+    assert!(bounced.x() > 0.);
+    let params = GiParams {
+        bounce: 0.,
+        ..GiParams::default()
+    };
+    assert_eq!(
+        dark.irradiance(vec2(0.6, 0.), &params, 0.5, |_| lit),
+        Vec3::zero()
+    );
+}
+
+#[test]
+fn temporal_accumulation() {
+    assert_eq!(history_weight(0), 1.);
+    assert_eq!(history_weight(1), 0.5);
+    assert_eq!(history_weight(1000), MIN_HISTORY_WEIGHT);
+    let offsets: Vec<_> = (0..8).map(frame_offset).collect();
+    assert!(offsets.iter().all(|o| *o >= 0. && *o < 1.));
+    assert!(offsets.windows(2).all(|w| (w[0] - w[1]).abs() > 0.1));
+}
@@ -0,0 +1,230 @@
+use crate::distance_field::DistanceField;
+use glam::{vec2, Vec2};
+
+/// Pixel coordinates of the nearest seed
+pub type Seed = Option<(u16, u16)>;
+
+/// Pixels of the mask boundary: occluder and free pixels which have a 4-neighbour of the other kind
+/// Pixels outside of the mask are treated as the border pixel (clamped), same as the seed shader
+pub fn boundary_seeds(mask: &[bool], width: usize, height: usize) -> Vec<Seed> {
+    let at = |x: isize, y: isize| {
+        let x = x.max(0).min(width as isize - 1) as usize;
+        let y = y.max(0).min(height as isize - 1) as usize;
+        mask[y * width + x]
+    };
+    let mut seeds = vec![None; width * height];
+    for y in 0..height as isize {
+        for x in 0..width as isize {
+            let this = at(x, y);
+            let boundary = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                .iter()
+                .any(|(dx, dy)| at(x + dx, y + dy) != this);
+            if boundary {
+                seeds[y as usize * width + x as usize] = Some((x as u16, y as u16));
+            }
+        }
+    }
+    seeds
+}
+
+/// Step lengths of the flood passes: half of the size rounded up to power of two, ..., 2, 1
+pub fn steps(width: usize, height: usize) -> Vec<usize> {
+    let mut step = width.max(height).next_power_of_two() / 2;
+    let mut steps = vec![];
+    while step > 0 {
+        steps.push(step);
+        step /= 2;
+    }
+    steps
+}
+
+/// One flood pass: every pixel takes the nearest seed among its own and the ones `step` pixels away
+/// Mirrors `jump_flood_shader::FLOOD`, neighbours are checked in the same order
+pub fn flood_pass(seeds: &[Seed], width: usize, height: usize, step: usize) -> Vec<Seed> {
+    let step = step as isize;
+    let mut result = vec![None; width * height];
+    for y in 0..height as isize {
+        for x in 0..width as isize {
+            let mut best = None;
+            let mut best_distance = f32::MAX;
+            for dy in [-step, 0, step].iter() {
+                for dx in [-step, 0, step].iter() {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if nx < 0 || ny < 0 || nx >= width as isize || ny >= height as isize {
+                        continue;
+                    }
+                    if let Some(seed) = seeds[ny as usize * width + nx as usize] {
+                        let distance = (seed_position(seed) - pixel_center(x, y)).length();
+                        if distance < best_distance {
+                            best = Some(seed);
+                            best_distance = distance;
+                        }
+                    }
+                }
+            }
+            result[y as usize * width + x as usize] = best;
+        }
+    }
+    result
+}
+
+/// Nearest boundary seed of every pixel after all flood passes
+pub fn jump_flood(mask: &[bool], width: usize, height: usize) -> Vec<Seed> {
+    let mut seeds = boundary_seeds(mask, width, height);
+    for step in steps(width, height) {
+        seeds = flood_pass(&seeds, width, height, step);
+    }
+    seeds
+}
+
+/// Signed distance in pixels (negative inside occluders) to the mask boundary, as the resolve shader computes it
+/// Without any boundary every pixel gets the diagonal of the mask
+pub fn signed_distances(mask: &[bool], width: usize, height: usize) -> Vec<f32> {
+    let far = vec2(width as f32, height as f32).length();
+    jump_flood(mask, width, height)
+        .iter()
+        .enumerate()
+        .map(|(i, seed)| {
+            let center = pixel_center((i % width) as isize, (i / width) as isize);
+            let distance = seed.map_or(far, |seed| (seed_position(seed) - center).length());
+            if mask[i] {
+                -distance
+            } else {
+                distance
+            }
+        })
+        .collect()
+}
+
+impl DistanceField {
+    /// Field of a binary occluder mask covering the world rect, computed by jump flooding
+    /// Rows of the mask go from the bottom, like GL textures
+    pub fn from_mask(
+        mask: &[bool],
+        width: usize,
+        height: usize,
+        rect: (Vec2, Vec2),
+    ) -> DistanceField {
+        let mut field = DistanceField::new(width, height, rect);
+        let texel = field.texel_size();
+        // distances are measured in pixels, texels are assumed to be square
+        let scale = (texel.x() + texel.y()) * 0.5;
+        field.data = signed_distances(mask, width, height)
+            .iter()
+            .map(|d| d * scale)
+            .collect();
+        field
+    }
+}
+
+fn pixel_center(x: isize, y: isize) -> Vec2 {
+    vec2(x as f32 + 0.5, y as f32 + 0.5)
+}
+
+fn seed_position(seed: (u16, u16)) -> Vec2 {
+    pixel_center(seed.0 as isize, seed.1 as isize)
+}
+
+#[cfg(test)]
+fn disk_mask(size: usize, center: Vec2, radius: f32) -> Vec<bool> {
+    (0..size * size)
+        .map(|i| {
+            (pixel_center((i % size) as isize, (i / size) as isize) - center).length() < radius
+        })
+        .collect()
+}
+
+#[cfg(test)]
+fn brute_force_distances(mask: &[bool], width: usize, height: usize) -> Vec<f32> {
+    let seeds = boundary_seeds(mask, width, height);
+    let seeds: Vec<_> = seeds.iter().filter_map(|seed| *seed).collect();
+    (0..width * height)
+        .map(|i| {
+            let center = pixel_center((i % width) as isize, (i / width) as isize);
+            let distance = seeds
+                .iter()
+                .map(|seed| (seed_position(*seed) - center).length())
+                .fold(f32::MAX, f32::min);
+            if mask[i] {
+                -distance
+            } else {
+                distance
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn flood_steps() {
+    assert_eq!(steps(64, 64), vec![32, 16, 8, 4, 2, 1]);
+    assert_eq!(steps(100, 30), vec![64, 32, 16, 8, 4, 2, 1]);
+    assert_eq!(steps(1, 1), Vec::<usize>::new());
+}
+
+#[test]
+fn seeds_on_boundary() {
+    // 4x4 with 2x2 occluder in the middle
+    #[rustfmt::skip]
+    let mask = [
+        false, false, false, false,
+        false, true,  true,  false,
+        false, true,  true,  false,
+        false, false, false, false,
+    ];
+    let seeds = boundary_seeds(&mask, 4, 4);
+    // every occluder pixel touches free space, corners don't touch the occluder
+    assert_eq!(seeds[5], Some((1, 1)));
+    assert_eq!(seeds[1], Some((1, 0)));
+    assert_eq!(seeds[0], None);
+    assert_eq!(seeds.iter().filter(|seed| seed.is_some()).count(), 12);
+}
+
+#[test]
+fn matches_brute_force() {
+    let (width, height) = (64, 48);
+    let mut mask = disk_mask(64, vec2(20., 20.), 9.);
+    mask.truncate(width * height);
+    // second occluder: a rect
+    for y in 30..40 {
+        for x in 40..60 {
+            mask[y * width + x] = true;
+        }
+    }
+    let flooded = signed_distances(&mask, width, height);
+    let exact = brute_force_distances(&mask, width, height);
+    let max_error = flooded
+        .iter()
+        .zip(exact.iter())
+        .map(|(a, b)| (a - b).abs())
+        .fold(0., f32::max);
+    // jump flooding is approximate, but errors are rare and small for simple shapes
+    assert!(max_error < 1., "max error {}", max_error);
+    for (a, b) in flooded.iter().zip(exact.iter()) {
+        assert_eq!(a.signum(), b.signum());
+    }
+}
+
+#[test]
+fn empty_mask() {
+    let distances = signed_distances(&[false; 16], 4, 4);
+    assert!(distances.iter().all(|d| *d == vec2(4., 4.).length()));
+}
+
+#[test]
+fn disk_field() {
+    let field = DistanceField::from_mask(
+        &disk_mask(128, vec2(64., 64.), 16.),
+        128,
+        128,
+        (vec2(-2., -2.), vec2(2., 2.)),
+    );
+    // disk of radius 0.5 in world units at the origin
+    let texel = field.texel_size().x();
+    assert!((field.sample(vec2(1., 0.)) - 0.5).abs() < 2. * texel);
+    assert!((field.sample(vec2(0., 0.)) + 0.5).abs() < 2. * texel);
+    assert!(field.sample(vec2(0., 0.45)) < 0.);
+    // works for the sphere tracing as well as the baked field
+    let light = vec2(-1.5, 0.);
+    assert_eq!(field.soft_shadow(vec2(1., 0.), light, 0.1), 0.);
+    assert_eq!(field.soft_shadow(vec2(1., 1.5), light, 0.1), 1.);
+}
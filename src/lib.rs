@@ -0,0 +1,42 @@
+//! 2D lighting and shadows on top of miniquad
+//!
+//! Renderers don't own the window: create them in your `EventHandler`
+//! and call their `draw` with the camera projection, see `examples/sample.rs`
+
+/// Simple orthographic camera
+pub mod camera;
+/// Signed distance field of occluders baked on CPU, soft shadows by sphere tracing it
+pub mod distance_field;
+/// Interactive placing and moving of occluders
+pub mod editor;
+/// Polygon routines: generation, visibility, shadow volumes, triangulation
+pub mod geometry;
+/// Renderers built on top of miniquad
+pub mod gfx;
+/// 2D global illumination: emitters and blockers in a distance field, light gathered by ray marching
+pub mod gi;
+/// CPU reference of jump flooding: signed distance field of a binary occluder mask
+pub mod jump_flood;
+/// Point, spot and directional lights
+pub mod light;
+/// Normal maps of sprites and their Lambert lighting
+pub mod normal_map;
+/// Rigid body physics of occluders: gravity, SAT collisions and impulses
+pub mod physics;
+/// Scene description stored in json
+pub mod scene;
+/// Signed distance shapes combined with boolean ops, evaluated on CPU or generated as GLSL
+pub mod sdf;
+/// Images decoded at runtime and deformed textured quads batched by texture
+pub mod sprite;
+/// Translation, rotation and scale of models
+pub mod transform;
+/// Occluders, lights and their shadows, no GL context needed
+pub mod world;
+
+pub use camera::Camera;
+pub use editor::Editor;
+pub use gfx::{deformed_texture::TextureRenderer, sdf::SdfRenderer, shadow::ShadowRenderer};
+pub use light::{Light, LightKind};
+pub use transform::Transform2D;
+pub use world::{Occluder, World};
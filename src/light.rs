@@ -0,0 +1,117 @@
+use crate::geometry::{directional_shadow_volume, nonzero_direction, shadow_volume, ShadowPoint};
+use crate::transform::Transform2D;
+use geo::Polygon;
+use glam::{vec2, vec3, Vec2, Vec3};
+
+#[derive(Clone, Copy, Debug)]
+pub enum LightKind {
+    /// Shines in all directions
+    Point,
+    /// Sun, parallel rays everywhere, position and radius are not used
+    Directional { direction: Vec2 },
+    /// Point light limited by cone, angles are measured from direction
+    /// Light fades out between inner and outer angles
+    Spot {
+        direction: Vec2,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Light {
+    pub position: Vec2,
+    pub radius: f32,
+    pub color: Vec3,
+    pub intensity: f32,
+    pub kind: LightKind,
+}
+
+impl Light {
+    /// Point light
+    pub fn new(position: Vec2, radius: f32) -> Self {
+        Light {
+            position,
+            radius,
+            color: vec3(1., 1., 1.),
+            intensity: 1.,
+            kind: LightKind::Point,
+        }
+    }
+
+    pub fn directional(direction: Vec2) -> Self {
+        Light {
+            kind: LightKind::Directional {
+                direction: nonzero_direction(direction),
+            },
+            ..Light::new(vec2(0., 0.), 0.)
+        }
+    }
+
+    pub fn spot(
+        position: Vec2,
+        radius: f32,
+        direction: Vec2,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Self {
+        Light {
+            kind: LightKind::Spot {
+                direction: nonzero_direction(direction),
+                inner_angle,
+                outer_angle,
+            },
+            ..Light::new(position, radius)
+        }
+    }
+
+    /// Shadow geometry of polygon (model coords) placed in the world by transform
+    pub fn shadow_volume(
+        &self,
+        polygon: &Polygon<f32>,
+        transform: &Transform2D,
+    ) -> Vec<ShadowPoint> {
+        match self.kind {
+            LightKind::Directional { direction } => {
+                directional_shadow_volume(polygon, transform, direction)
+            }
+            _ => shadow_volume(polygon, transform, self.position, self.radius),
+        }
+    }
+}
+
+#[test]
+fn directional_light_ignores_position() {
+    let square = Polygon::new(
+        vec![(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)].into(),
+        vec![],
+    );
+    let mut light = Light::directional(vec2(1., 0.));
+    let shadow = light.shadow_volume(&square, &Transform2D::default());
+    light.position = vec2(3., 3.);
+    let moved = light.shadow_volume(&square, &Transform2D::default());
+    assert!(!shadow.is_empty());
+    assert_eq!(shadow.len(), moved.len());
+    for (a, b) in shadow.iter().zip(moved.iter()) {
+        assert!((a.pos - b.pos).length() < 1E-6);
+    }
+}
+
+#[test]
+fn zero_direction_light() {
+    let square = Polygon::new(
+        vec![(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)].into(),
+        vec![],
+    );
+    let sun = Light::directional(vec2(0., 0.));
+    let shadow = sun.shadow_volume(&square, &Transform2D::default());
+    assert!(!shadow.is_empty());
+    assert!(shadow
+        .iter()
+        .all(|p| p.pos.x().is_finite() && p.pos.y().is_finite()));
+    if let LightKind::Spot { direction, .. } =
+        Light::spot(vec2(0., 0.), 0.1, vec2(0., 0.), 0.3, 0.6).kind
+    {
+        assert!(direction.length() > 0.5);
+    }
+}
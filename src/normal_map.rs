@@ -0,0 +1,106 @@
+use crate::geometry::nonzero_direction;
+use crate::light::{Light, LightKind};
+use glam::{vec3, Vec3};
+
+/// Tangent space normals (RGB, green points up in the image) with height in alpha, made from brightness of the image
+/// Bigger strength makes slopes steeper, pixels outside of the image repeat the border
+pub fn normal_map_from_brightness(
+    rgba: &[u8],
+    width: usize,
+    height: usize,
+    strength: f32,
+) -> Vec<u8> {
+    let brightness = |x: isize, y: isize| {
+        let x = x.max(0).min(width as isize - 1) as usize;
+        let y = y.max(0).min(height as isize - 1) as usize;
+        let pixel = &rgba[4 * (y * width + x)..];
+        (0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32) / 255.
+    };
+    let byte = |v: f32| ((v * 0.5 + 0.5) * 255.).round() as u8;
+    let mut normals = Vec::with_capacity(width * height * 4);
+    for y in 0..height as isize {
+        for x in 0..width as isize {
+            let dx = brightness(x + 1, y) - brightness(x - 1, y);
+            // rows go down the image
+            let dy = brightness(x, y - 1) - brightness(x, y + 1);
+            let normal = vec3(-dx * strength, -dy * strength, 1.).normalize();
+            normals.extend_from_slice(&[
+                byte(normal.x()),
+                byte(normal.y()),
+                byte(normal.z()),
+                (brightness(x, y) * 255.).round() as u8,
+            ]);
+        }
+    }
+    normals
+}
+
+/// Lambert term of the light at a surface point (world xy and height above the ground) with the normal
+/// Lights hang `light_height` above the ground, the sun comes from that height over a unit distance
+/// Mirrors the accumulate shader of `ShadowRenderer`
+pub fn lambert(normal: Vec3, point: Vec3, light: &Light, light_height: f32) -> f32 {
+    let to_light = match light.kind {
+        LightKind::Directional { direction } => {
+            let direction = -nonzero_direction(direction).normalize();
+            vec3(direction.x(), direction.y(), light_height)
+        }
+        _ => vec3(
+            light.position.x() - point.x(),
+            light.position.y() - point.y(),
+            light_height - point.z(),
+        ),
+    };
+    normal.normalize().dot(to_light.normalize()).max(0.)
+}
+
+#[test]
+fn flat_image() {
+    let gray = [100u8, 100, 100, 255].repeat(9);
+    let normals = normal_map_from_brightness(&gray, 3, 3, 4.);
+    for pixel in normals.chunks(4) {
+        assert_eq!(pixel, &[128, 128, 255, 100]);
+    }
+}
+
+#[test]
+fn slopes() {
+    // brighter to the right and to the top
+    let (width, height) = (4, 4);
+    let mut rgba = vec![];
+    for y in 0..height {
+        for x in 0..width {
+            let v = (x * 40 + (height - 1 - y) * 20) as u8;
+            rgba.extend_from_slice(&[v, v, v, 255]);
+        }
+    }
+    let normals = normal_map_from_brightness(&rgba, width, height, 4.);
+    let pixel = &normals[4 * (width + 1)..4 * (width + 2)];
+    // surface rises to the right and up, so it faces left and down
+    assert!(pixel[0] < 128 && pixel[1] < 128 && pixel[2] > 128);
+    // steeper along x
+    assert!(pixel[0] < pixel[1]);
+}
+
+#[test]
+fn lambert_term() {
+    use glam::vec2;
+    let light = Light::new(vec2(0., 0.), 0.1);
+    let up = vec3(0., 0., 1.);
+    assert!((lambert(up, vec3(0., 0., 0.), &light, 1.) - 1.).abs() < 1E-6);
+    // facing the light is brighter than facing away
+    let point = vec3(1., 0., 0.);
+    let towards = lambert(vec3(-1., 0., 1.), point, &light, 1.);
+    let away = lambert(vec3(1., 0., 1.), point, &light, 1.);
+    assert!(towards > lambert(up, point, &light, 1.) && away < towards);
+    assert_eq!(lambert(vec3(1., 0., 0.), point, &light, 0.), 0.);
+    // higher surface sees the light from a lower angle
+    let high = lambert(up, vec3(1., 0., 0.5), &light, 1.);
+    assert!(high < lambert(up, point, &light, 1.));
+    // sun ignores position
+    let sun = Light::directional(vec2(1., 0.));
+    assert_eq!(
+        lambert(up, point, &sun, 1.),
+        lambert(up, vec3(5., 5., 0.), &sun, 1.)
+    );
+    assert!(lambert(vec3(-1., 0., 0.), point, &sun, 1.) > 0.);
+}
@@ -0,0 +1,564 @@
+use crate::world::Occluder;
+use geo::convexhull::ConvexHull;
+use geo::Polygon;
+use glam::{vec2, Vec2};
+
+/// Collision resolution passes per step
+const SOLVER_ITERATIONS: usize = 4;
+/// Penetration which is left without positional correction, prevents jitter
+const PENETRATION_SLOP: f32 = 0.005;
+/// Part of the penetration removed by each pass
+const CORRECTION_PERCENT: f32 = 0.4;
+
+#[derive(Clone, Copy, Debug)]
+pub struct PhysicsParams {
+    pub gravity: Vec2,
+    /// Bounciness, 0 -- no bounce, 1 -- elastic
+    pub restitution: f32,
+    /// Coulomb friction coefficient
+    pub friction: f32,
+}
+
+impl Default for PhysicsParams {
+    fn default() -> PhysicsParams {
+        PhysicsParams {
+            gravity: vec2(0., -3.),
+            restitution: 0.5,
+            friction: 0.3,
+        }
+    }
+}
+
+/// Mass properties of occluder, computed from its scaled convex hull with unit density
+/// Bodies rotate about the centroid of the hull, occluder's velocity is the velocity of the centroid
+#[derive(Clone, Debug)]
+pub struct Body {
+    /// Convex hull in model coords (without closing point), counter clockwise after scaling
+    pub hull: Vec<Vec2>,
+    /// Centroid of the hull in model coords
+    pub center: Vec2,
+    pub inv_mass: f32,
+    /// Inverse inertia about the centroid
+    pub inv_inertia: f32,
+}
+
+impl Body {
+    pub fn new(occluder: &Occluder) -> Body {
+        let mut hull: Vec<Vec2> = occluder
+            .polygon
+            .convex_hull()
+            .exterior()
+            .points_iter()
+            .map(|p| vec2(p.x(), p.y()))
+            .collect();
+        if hull.len() > 1 && hull.first() == hull.last() {
+            hull.pop();
+        }
+        let scale = occluder.transform.scale;
+        // area, its first moment and inertia about the model origin, signed by the winding
+        let (mut area, mut moment, mut inertia) = (0., vec2(0., 0.), 0.);
+        for (i, p) in hull.iter().enumerate() {
+            let (p, q) = (*p * scale, hull[(i + 1) % hull.len()] * scale);
+            let cross = cross(p, q);
+            area += cross / 2.;
+            moment += (p + q) * cross / 6.;
+            inertia += cross * (p.dot(p) + p.dot(q) + q.dot(q)) / 12.;
+        }
+        if area < 0. {
+            hull.reverse();
+        }
+        let mass = area.abs();
+        if occluder.fixed || mass < f32::EPSILON {
+            return Body {
+                hull,
+                center: vec2(0., 0.),
+                inv_mass: 0.,
+                inv_inertia: 0.,
+            };
+        }
+        let center = moment / area;
+        // parallel axis theorem
+        let inertia = inertia.abs() - mass * center.dot(center);
+        Body {
+            hull,
+            center: center / scale,
+            inv_mass: 1. / mass,
+            inv_inertia: 1. / inertia,
+        }
+    }
+
+    /// Centroid in world coords
+    fn world_center(&self, occluder: &Occluder) -> Vec2 {
+        occluder.transform.apply(self.center)
+    }
+
+    /// Hull in world coords
+    fn world_hull(&self, occluder: &Occluder) -> Vec<Vec2> {
+        self.hull
+            .iter()
+            .map(|p| occluder.transform.apply(*p))
+            .collect()
+    }
+}
+
+/// Bodies of occluders by index, kept between steps
+/// A body is rebuilt only when polygon, scale or fixed flag of its occluder changes
+#[derive(Clone, Debug, Default)]
+pub struct Bodies {
+    bodies: Vec<Body>,
+    /// Polygon, scale and fixed flag each body is built from
+    sources: Vec<(Polygon<f32>, Vec2, bool)>,
+}
+
+impl Bodies {
+    /// Bodies of the occluders, same order
+    pub fn update(&mut self, occluders: &[Occluder]) -> &[Body] {
+        self.bodies.truncate(occluders.len());
+        self.sources.truncate(occluders.len());
+        for (i, occluder) in occluders.iter().enumerate() {
+            let scale = occluder.transform.scale;
+            let fresh = match self.sources.get(i) {
+                Some((polygon, source_scale, fixed)) => {
+                    *polygon == occluder.polygon
+                        && *source_scale == scale
+                        && *fixed == occluder.fixed
+                }
+                None => false,
+            };
+            if fresh {
+                continue;
+            }
+            let body = Body::new(occluder);
+            let source = (occluder.polygon.clone(), scale, occluder.fixed);
+            if i < self.bodies.len() {
+                self.bodies[i] = body;
+                self.sources[i] = source;
+            } else {
+                self.bodies.push(body);
+                self.sources.push(source);
+            }
+        }
+        &self.bodies
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Contact {
+    /// Unit normal pointing from the first body to the second
+    pub normal: Vec2,
+    pub penetration: f32,
+    pub point: Vec2,
+}
+
+/// Separating axis test of two counter clockwise convex polygons
+pub fn collide(a: &[Vec2], b: &[Vec2]) -> Option<Contact> {
+    let (separation_a, edge_a) = max_separation(a, b);
+    if separation_a > 0. {
+        return None;
+    }
+    let (separation_b, edge_b) = max_separation(b, a);
+    if separation_b > 0. {
+        return None;
+    }
+    // the face with the least penetration is the reference one
+    let (reference, incident, edge, flip) = if separation_a >= separation_b {
+        (a, b, edge_a, false)
+    } else {
+        (b, a, edge_b, true)
+    };
+    let start = reference[edge];
+    let normal = outward_normal(start, reference[(edge + 1) % reference.len()]);
+    let point = deep_points_center(incident, start, normal)?;
+    Some(Contact {
+        normal: if flip { -normal } else { normal },
+        penetration: -separation_a.max(separation_b),
+        point,
+    })
+}
+
+/// Contact of the polygon with the half plane { p | dot(p - origin, normal) >= 0 }
+fn collide_wall(polygon: &[Vec2], origin: Vec2, normal: Vec2) -> Option<Contact> {
+    let depth = polygon
+        .iter()
+        .map(|p| normal.dot(*p - origin))
+        .fold(f32::MAX, f32::min);
+    if depth >= 0. {
+        return None;
+    }
+    Some(Contact {
+        normal,
+        penetration: -depth,
+        point: deep_points_center(polygon, origin, normal)?,
+    })
+}
+
+/// Max over edges of `a` of the distance from the edge to the nearest point of `b`,
+/// negative if `b` penetrates the edge
+fn max_separation(a: &[Vec2], b: &[Vec2]) -> (f32, usize) {
+    let mut best = (f32::MIN, 0);
+    for i in 0..a.len() {
+        let start = a[i];
+        let normal = outward_normal(start, a[(i + 1) % a.len()]);
+        let separation = b
+            .iter()
+            .map(|p| normal.dot(*p - start))
+            .fold(f32::MAX, f32::min);
+        if separation > best.0 {
+            best = (separation, i);
+        }
+    }
+    best
+}
+
+/// Center of points behind the line
+fn deep_points_center(points: &[Vec2], origin: Vec2, normal: Vec2) -> Option<Vec2> {
+    let deep: Vec<_> = points
+        .iter()
+        .filter(|p| normal.dot(**p - origin) < 0.)
+        .collect();
+    if deep.is_empty() {
+        return None;
+    }
+    Some(deep.iter().fold(vec2(0., 0.), |sum, p| sum + **p) / deep.len() as f32)
+}
+
+/// Integrate occluders and resolve their collisions with each other and with the bounds
+/// `bodies` caches mass properties of the occluders between steps
+pub fn step(
+    occluders: &mut [Occluder],
+    bodies: &mut Bodies,
+    bounds: (Vec2, Vec2),
+    params: &PhysicsParams,
+    dt: f32,
+) {
+    let bodies = bodies.update(occluders);
+    for (occluder, body) in occluders.iter_mut().zip(bodies.iter()) {
+        if body.inv_mass == 0. {
+            continue;
+        }
+        occluder.velocity += params.gravity * dt;
+        // rotate about the centroid, translation is the model origin
+        let center = body.world_center(occluder) + occluder.velocity * dt;
+        occluder.transform.rotation += occluder.angular_velocity * dt;
+        occluder.transform.translation = center - occluder.transform.apply_linear(body.center);
+    }
+    let (min, max) = bounds;
+    let walls = [
+        (min, vec2(1., 0.)),
+        (min, vec2(0., 1.)),
+        (max, vec2(-1., 0.)),
+        (max, vec2(0., -1.)),
+    ];
+    // world hulls are updated only for bodies moved by collisions
+    let mut hulls: Vec<_> = bodies
+        .iter()
+        .zip(occluders.iter())
+        .map(|(body, occluder)| body.world_hull(occluder))
+        .collect();
+    for _ in 0..SOLVER_ITERATIONS {
+        for i in 0..occluders.len() {
+            if bodies[i].inv_mass == 0. {
+                continue;
+            }
+            for (origin, normal) in walls.iter() {
+                if let Some(contact) = collide_wall(&hulls[i], *origin, *normal) {
+                    resolve(None, (&mut occluders[i], &bodies[i]), &contact, params);
+                    hulls[i] = bodies[i].world_hull(&occluders[i]);
+                }
+            }
+        }
+        for i in 0..occluders.len() {
+            for j in i + 1..occluders.len() {
+                if bodies[i].inv_mass == 0. && bodies[j].inv_mass == 0. {
+                    continue;
+                }
+                if let Some(contact) = collide(&hulls[i], &hulls[j]) {
+                    let (head, tail) = occluders.split_at_mut(j);
+                    resolve(
+                        Some((&mut head[i], &bodies[i])),
+                        (&mut tail[0], &bodies[j]),
+                        &contact,
+                        params,
+                    );
+                    hulls[i] = bodies[i].world_hull(&occluders[i]);
+                    hulls[j] = bodies[j].world_hull(&occluders[j]);
+                }
+            }
+        }
+    }
+}
+
+/// Velocity state of a colliding body at the contact point
+struct Side {
+    inv_mass: f32,
+    inv_inertia: f32,
+    /// From the centroid to the contact point
+    radius: Vec2,
+    velocity: Vec2,
+    angular_velocity: f32,
+}
+
+impl Side {
+    fn new(occluder: &Occluder, body: &Body, point: Vec2) -> Side {
+        Side {
+            inv_mass: body.inv_mass,
+            inv_inertia: body.inv_inertia,
+            radius: point - body.world_center(occluder),
+            velocity: occluder.velocity,
+            angular_velocity: occluder.angular_velocity,
+        }
+    }
+
+    /// Static wall
+    fn wall() -> Side {
+        Side {
+            inv_mass: 0.,
+            inv_inertia: 0.,
+            radius: vec2(0., 0.),
+            velocity: vec2(0., 0.),
+            angular_velocity: 0.,
+        }
+    }
+
+    fn point_velocity(&self) -> Vec2 {
+        self.velocity + perp(self.radius) * self.angular_velocity
+    }
+
+    fn apply(&mut self, impulse: Vec2) {
+        self.velocity += impulse * self.inv_mass;
+        self.angular_velocity += cross(self.radius, impulse) * self.inv_inertia;
+    }
+
+    /// Inverse mass along the direction, including rotation
+    fn inv_mass_along(&self, direction: Vec2) -> f32 {
+        self.inv_mass + cross(self.radius, direction).powi(2) * self.inv_inertia
+    }
+}
+
+/// Apply collision and friction impulses and push bodies apart
+/// `a` is None for static walls
+fn resolve(
+    a: Option<(&mut Occluder, &Body)>,
+    b: (&mut Occluder, &Body),
+    contact: &Contact,
+    params: &PhysicsParams,
+) {
+    let normal = contact.normal;
+    let mut side_a = a
+        .as_ref()
+        .map_or_else(Side::wall, |(a, body)| Side::new(a, body, contact.point));
+    let mut side_b = Side::new(b.0, b.1, contact.point);
+    let inv_mass_sum = side_a.inv_mass + side_b.inv_mass;
+    if inv_mass_sum == 0. {
+        return;
+    }
+    let normal_velocity = (side_b.point_velocity() - side_a.point_velocity()).dot(normal);
+    // bodies which are separating already keep their velocities
+    if normal_velocity < 0. {
+        let impulse = -(1. + params.restitution) * normal_velocity
+            / (side_a.inv_mass_along(normal) + side_b.inv_mass_along(normal));
+        side_a.apply(-normal * impulse);
+        side_b.apply(normal * impulse);
+        let velocity = side_b.point_velocity() - side_a.point_velocity();
+        let tangent = velocity - normal * velocity.dot(normal);
+        if tangent.length() > f32::EPSILON {
+            let tangent = tangent.normalize();
+            let friction = -velocity.dot(tangent)
+                / (side_a.inv_mass_along(tangent) + side_b.inv_mass_along(tangent));
+            let max_friction = impulse * params.friction;
+            let friction = friction.max(-max_friction).min(max_friction);
+            side_a.apply(-tangent * friction);
+            side_b.apply(tangent * friction);
+        }
+    }
+    let correction = normal
+        * ((contact.penetration - PENETRATION_SLOP).max(0.) / inv_mass_sum * CORRECTION_PERCENT);
+    if let Some((a, _)) = a {
+        a.velocity = side_a.velocity;
+        a.angular_velocity = side_a.angular_velocity;
+        a.transform.translation -= correction * side_a.inv_mass;
+    }
+    let b = b.0;
+    b.velocity = side_b.velocity;
+    b.angular_velocity = side_b.angular_velocity;
+    b.transform.translation += correction * side_b.inv_mass;
+}
+
+fn cross(a: Vec2, b: Vec2) -> f32 {
+    a.x() * b.y() - a.y() * b.x()
+}
+
+/// Rotated by 90 degrees counter clockwise, cross product of angular velocity and radius
+fn perp(v: Vec2) -> Vec2 {
+    vec2(-v.y(), v.x())
+}
+
+/// Outward normal of counter clockwise polygon's edge
+fn outward_normal(start: Vec2, end: Vec2) -> Vec2 {
+    let edge = end - start;
+    vec2(edge.y(), -edge.x()).normalize()
+}
+
+#[cfg(test)]
+fn square(size: f32, position: Vec2, fixed: bool) -> Occluder {
+    use crate::transform::Transform2D;
+    use geo::Polygon;
+    Occluder {
+        polygon: Polygon::new(
+            vec![(-size, -size), (size, -size), (size, size), (-size, size)].into(),
+            vec![],
+        ),
+        transform: Transform2D::from_translation(position),
+        velocity: vec2(0., 0.),
+        angular_velocity: 0.,
+        fixed,
+        emission: glam::Vec3::zero(),
+    }
+}
+
+#[test]
+fn overlapping_squares_collide() {
+    let a = square(0.5, vec2(0., 0.), false);
+    let b = square(0.5, vec2(0.8, 0.1), false);
+    let (body_a, body_b) = (Body::new(&a), Body::new(&b));
+    let contact = collide(&body_a.world_hull(&a), &body_b.world_hull(&b)).unwrap();
+    assert!((contact.normal - vec2(1., 0.)).length() < 1E-5);
+    assert!((contact.penetration - 0.2).abs() < 1E-5);
+    // on the incident face inside the reference one
+    assert!((contact.point.x() - 0.3).abs() < 1E-5);
+    assert!(contact.point.y() > -0.5 && contact.point.y() < 0.6);
+}
+
+#[test]
+fn separated_squares_dont_collide() {
+    let a = square(0.5, vec2(0., 0.), false);
+    let b = square(0.5, vec2(0.7, 1.2), false);
+    let (body_a, body_b) = (Body::new(&a), Body::new(&b));
+    assert!(collide(&body_a.world_hull(&a), &body_b.world_hull(&b)).is_none());
+}
+
+#[test]
+fn square_mass() {
+    let body = Body::new(&square(0.5, vec2(3., 3.), false));
+    assert!((body.inv_mass - 1.).abs() < 1E-5);
+    // m * (w^2 + h^2) / 12
+    assert!((body.inv_inertia - 6.).abs() < 1E-4);
+    assert_eq!(Body::new(&square(0.5, vec2(0., 0.), true)).inv_mass, 0.);
+}
+
+#[test]
+fn square_rests_on_floor() {
+    let mut occluders = vec![square(0.5, vec2(0., 1.), false)];
+    let bounds = (vec2(-2., -2.), vec2(2., 2.));
+    let params = PhysicsParams::default();
+    let mut bodies = Bodies::default();
+    for _ in 0..600 {
+        step(&mut occluders, &mut bodies, bounds, &params, 1. / 60.);
+    }
+    let square = &occluders[0];
+    assert!((square.transform.translation.y() + 1.5).abs() < 0.05);
+    assert!(square.velocity.length() < 0.1);
+    assert!(square.transform.rotation.abs() < 0.05);
+}
+
+#[test]
+fn fixed_occluder_stops_falling_one() {
+    let mut occluders = vec![
+        square(1., vec2(0., -1.), true),
+        square(0.25, vec2(0.5, 0.5), false),
+    ];
+    let bounds = (vec2(-5., -5.), vec2(5., 5.));
+    let params = PhysicsParams::default();
+    let mut bodies = Bodies::default();
+    for _ in 0..600 {
+        step(&mut occluders, &mut bodies, bounds, &params, 1. / 60.);
+    }
+    assert_eq!(occluders[0].transform.translation, vec2(0., -1.));
+    assert!((occluders[1].transform.translation.y() - 0.25).abs() < 0.05);
+}
+
+#[test]
+fn collision_transfers_momentum() {
+    let mut occluders = vec![
+        square(0.25, vec2(-1., 0.), false),
+        square(0.25, vec2(0., 0.), false),
+    ];
+    occluders[0].velocity = vec2(2., 0.);
+    let params = PhysicsParams {
+        gravity: vec2(0., 0.),
+        restitution: 1.,
+        friction: 0.,
+    };
+    let bounds = (vec2(-5., -5.), vec2(5., 5.));
+    let mut bodies = Bodies::default();
+    for _ in 0..60 {
+        step(&mut occluders, &mut bodies, bounds, &params, 1. / 60.);
+    }
+    assert!(occluders[0].velocity.x().abs() < 0.1);
+    assert!((occluders[1].velocity.x() - 2.).abs() < 0.1);
+}
+
+#[test]
+fn scaled_and_mirrored_mass() {
+    let mut occluder = square(0.5, vec2(0., 0.), false);
+    occluder.transform.scale = vec2(-2., 1.);
+    let body = Body::new(&occluder);
+    assert!((body.inv_mass - 0.5).abs() < 1E-5);
+    let hull = body.world_hull(&occluder);
+    // still counter clockwise in the world
+    let area: f32 = (0..hull.len())
+        .map(|i| cross(hull[i], hull[(i + 1) % hull.len()]))
+        .sum();
+    assert!(area > 0.);
+}
+
+#[test]
+fn off_center_model_spins_about_centroid() {
+    use crate::geometry::generate_l_polygon;
+    let mut occluder = square(0.5, vec2(0., 0.), false);
+    occluder.polygon = generate_l_polygon(0.5, 0.2);
+    occluder.angular_velocity = 3.;
+    let body = Body::new(&occluder);
+    // hull of the L leans to its corner
+    assert!(body.center.x() < -0.05 && body.center.y() < -0.05);
+    let start = body.world_center(&occluder);
+    let mut occluders = vec![occluder];
+    let params = PhysicsParams {
+        gravity: vec2(0., 0.),
+        ..PhysicsParams::default()
+    };
+    let bounds = (vec2(-5., -5.), vec2(5., 5.));
+    let mut bodies = Bodies::default();
+    for _ in 0..60 {
+        step(&mut occluders, &mut bodies, bounds, &params, 1. / 60.);
+    }
+    assert!((body.world_center(&occluders[0]) - start).length() < 1E-4);
+    assert!((occluders[0].transform.rotation - 3.).abs() < 1E-4);
+}
+
+#[test]
+fn inertia_about_centroid() {
+    use crate::transform::Transform2D;
+    let mut shifted = square(0.5, vec2(0., 0.), false);
+    shifted.polygon = Transform2D::from_translation(vec2(2., 1.)).apply_polygon(&shifted.polygon);
+    let body = Body::new(&shifted);
+    assert!((body.center - vec2(2., 1.)).length() < 1E-5);
+    assert!((body.inv_inertia - 6.).abs() < 1E-3);
+}
+
+#[test]
+fn bodies_rebuilt_on_change() {
+    let mut occluders = vec![square(0.5, vec2(0., 0.), false)];
+    let mut bodies = Bodies::default();
+    assert!((bodies.update(&occluders)[0].inv_mass - 1.).abs() < 1E-5);
+    occluders[0].transform.translation = vec2(1., 1.);
+    assert!((bodies.update(&occluders)[0].inv_mass - 1.).abs() < 1E-5);
+    occluders[0].transform.scale = vec2(2., 1.);
+    assert!((bodies.update(&occluders)[0].inv_mass - 0.5).abs() < 1E-5);
+    occluders[0].fixed = true;
+    assert_eq!(bodies.update(&occluders)[0].inv_mass, 0.);
+    occluders.push(square(0.25, vec2(0., 0.), false));
+    assert_eq!(bodies.update(&occluders).len(), 2);
+    occluders.clear();
+    assert!(bodies.update(&occluders).is_empty());
+}
@@ -0,0 +1,594 @@
+use geo::{LineString, Polygon};
+use glam::{vec2, vec3, Vec2};
+use quad_rand as qrand;
+use serde::{Deserialize, Serialize};
+
+use crate::geometry::{generate_convex_polygon, generate_l_polygon, nonzero_direction};
+use crate::light::{Light, LightKind};
+use crate::sdf::{SdfOp, SdfPrimitive, SdfShape};
+use crate::transform::Transform2D;
+use crate::world::{Occluder, World};
+
+/// Scene of the sample, loaded when no scene file is given
+pub const DEFAULT_SCENE: &str = include_str!("../scenes/default.json");
+
+/// Declarative description of the scene (stored as json)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SceneDesc {
+    #[serde(default)]
+    pub ambient: [f32; 3],
+    #[serde(default)]
+    pub occluders: Vec<OccluderDesc>,
+    /// The first light follows the mouse cursor
+    #[serde(default)]
+    pub lights: Vec<LightDesc>,
+    /// Deformed texture quads
+    #[serde(default)]
+    pub textures: Vec<TextureQuadDesc>,
+    #[serde(default)]
+    pub sdf_shapes: Vec<SdfShapeDesc>,
+    /// [min, max] corners of the world box
+    #[serde(default)]
+    pub bounds: Option<[[f32; 2]; 2]>,
+    #[serde(default)]
+    pub gravity: Option<[f32; 2]>,
+}
+
+/// Shadow casting polygon, coords of rings are in model space (without closing point)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OccluderDesc {
+    pub exterior: Vec<[f32; 2]>,
+    #[serde(default)]
+    pub holes: Vec<Vec<[f32; 2]>>,
+    pub position: [f32; 2],
+    #[serde(default)]
+    pub angle: f32,
+    #[serde(default = "unit_scale")]
+    pub scale: [f32; 2],
+    #[serde(default)]
+    pub velocity: [f32; 2],
+    #[serde(default)]
+    pub angular_velocity: f32,
+    /// Not moved by physics
+    #[serde(default)]
+    pub fixed: bool,
+    /// Light emitted by the polygon in GI mode
+    #[serde(default)]
+    pub emission: [f32; 3],
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub enum LightKindDesc {
+    Point,
+    Directional {
+        direction: [f32; 2],
+    },
+    Spot {
+        direction: [f32; 2],
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+// `#[default]` variants need a newer compiler than the rest of the dependencies
+#[allow(clippy::derivable_impls)]
+impl Default for LightKindDesc {
+    fn default() -> LightKindDesc {
+        LightKindDesc::Point
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct LightDesc {
+    #[serde(default)]
+    pub position: [f32; 2],
+    #[serde(default)]
+    pub radius: f32,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    #[serde(default)]
+    pub kind: LightKindDesc,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct TextureQuadDesc {
+    pub position: [f32; 2],
+    pub size: f32,
+    /// RGBA multiplier of the texture
+    #[serde(default = "opaque_white")]
+    pub tint: [f32; 4],
+}
+
+/// Signed distance shape, old scenes without primitive get the white triangle
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SdfShapeDesc {
+    pub position: [f32; 2],
+    #[serde(default)]
+    pub angle: f32,
+    #[serde(default = "unit_scale")]
+    pub scale: [f32; 2],
+    #[serde(default = "white")]
+    pub color: [f32; 3],
+    #[serde(default)]
+    pub primitive: SdfPrimitiveDesc,
+    #[serde(default)]
+    pub op: SdfOpDesc,
+    #[serde(default)]
+    pub emission: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub enum SdfPrimitiveDesc {
+    Circle {
+        radius: f32,
+    },
+    Box {
+        half_size: [f32; 2],
+    },
+    RoundedBox {
+        half_size: [f32; 2],
+        radius: f32,
+    },
+    Segment {
+        a: [f32; 2],
+        b: [f32; 2],
+        radius: f32,
+    },
+    ConvexPolygon {
+        points: Vec<[f32; 2]>,
+    },
+    EquilateralTriangle {
+        half_side: f32,
+    },
+}
+
+impl Default for SdfPrimitiveDesc {
+    fn default() -> SdfPrimitiveDesc {
+        SdfPrimitiveDesc::EquilateralTriangle { half_side: 0.5 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub enum SdfOpDesc {
+    Union,
+    Intersection,
+    Subtraction,
+    SmoothUnion {
+        k: f32,
+    },
+}
+
+// `#[default]` variants need a newer compiler than the rest of the dependencies
+#[allow(clippy::derivable_impls)]
+impl Default for SdfOpDesc {
+    fn default() -> SdfOpDesc {
+        SdfOpDesc::Union
+    }
+}
+
+fn unit_scale() -> [f32; 2] {
+    [1., 1.]
+}
+
+fn opaque_white() -> [f32; 4] {
+    [1., 1., 1., 1.]
+}
+
+fn white() -> [f32; 3] {
+    [1., 1., 1.]
+}
+
+impl SceneDesc {
+    pub fn from_json(json: &str) -> Result<SceneDesc, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn default_scene() -> SceneDesc {
+        SceneDesc::from_json(DEFAULT_SCENE).expect("default scene is broken")
+    }
+
+    /// Scene with the current state of the world (textures and sdf shapes are left empty)
+    pub fn from_world(world: &World) -> SceneDesc {
+        let (min, max) = world.bounds;
+        SceneDesc {
+            ambient: [world.ambient.x(), world.ambient.y(), world.ambient.z()],
+            occluders: world.occluders.iter().map(OccluderDesc::from).collect(),
+            lights: world.lights.iter().map(LightDesc::from).collect(),
+            textures: vec![],
+            sdf_shapes: vec![],
+            bounds: Some([vec2_to_desc(min), vec2_to_desc(max)]),
+            gravity: Some(vec2_to_desc(world.physics.gravity)),
+        }
+    }
+}
+
+/// Random convex polygons (and one L-shaped wall) thrown up
+pub fn random_occluders(n: usize) -> Vec<OccluderDesc> {
+    (0..n as i32)
+        .map(|i| {
+            let vel = vec2(0., qrand::gen_range(0.6, 1.2));
+            let polygon = match i {
+                0 => generate_l_polygon(0.3, 0.1),
+                _ => generate_convex_polygon(10, 0.3),
+            };
+            let pos = vec2(
+                (2 * i - (n + 1) as i32 / 2) as f32 * 2. / n as f32,
+                0.5 * vel.y(),
+            );
+            OccluderDesc::from_polygon(&polygon, pos, vel)
+        })
+        .collect()
+}
+
+fn ring_from_desc(points: &[[f32; 2]]) -> LineString<f32> {
+    points
+        .iter()
+        .map(|p| (p[0], p[1]))
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// Points of the ring without closing point
+fn ring_to_desc(ring: &LineString<f32>) -> Vec<[f32; 2]> {
+    let mut points: Vec<_> = ring.0.iter().map(|c| [c.x, c.y]).collect();
+    if points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    points
+}
+
+pub fn vec2_from_desc(v: [f32; 2]) -> Vec2 {
+    vec2(v[0], v[1])
+}
+
+pub fn vec2_to_desc(v: Vec2) -> [f32; 2] {
+    [v.x(), v.y()]
+}
+
+impl OccluderDesc {
+    pub fn from_polygon(polygon: &Polygon<f32>, position: Vec2, velocity: Vec2) -> OccluderDesc {
+        OccluderDesc {
+            exterior: ring_to_desc(polygon.exterior()),
+            holes: polygon.interiors().iter().map(ring_to_desc).collect(),
+            position: vec2_to_desc(position),
+            angle: 0.,
+            scale: unit_scale(),
+            velocity: vec2_to_desc(velocity),
+            angular_velocity: 0.,
+            fixed: false,
+            emission: [0., 0., 0.],
+        }
+    }
+
+    pub fn polygon(&self) -> Polygon<f32> {
+        Polygon::new(
+            ring_from_desc(&self.exterior),
+            self.holes.iter().map(|hole| ring_from_desc(hole)).collect(),
+        )
+    }
+}
+
+impl From<&Occluder> for OccluderDesc {
+    fn from(occluder: &Occluder) -> OccluderDesc {
+        let transform = &occluder.transform;
+        OccluderDesc {
+            angle: transform.rotation,
+            scale: vec2_to_desc(transform.scale),
+            angular_velocity: occluder.angular_velocity,
+            fixed: occluder.fixed,
+            emission: [
+                occluder.emission.x(),
+                occluder.emission.y(),
+                occluder.emission.z(),
+            ],
+            ..OccluderDesc::from_polygon(
+                &occluder.polygon,
+                transform.translation,
+                occluder.velocity,
+            )
+        }
+    }
+}
+
+impl From<&LightDesc> for Light {
+    fn from(desc: &LightDesc) -> Light {
+        let kind = match desc.kind {
+            LightKindDesc::Point => LightKind::Point,
+            LightKindDesc::Directional { direction } => LightKind::Directional {
+                direction: nonzero_direction(vec2_from_desc(direction)),
+            },
+            LightKindDesc::Spot {
+                direction,
+                inner_angle,
+                outer_angle,
+            } => LightKind::Spot {
+                direction: nonzero_direction(vec2_from_desc(direction)),
+                inner_angle,
+                outer_angle,
+            },
+        };
+        Light {
+            position: vec2_from_desc(desc.position),
+            radius: desc.radius,
+            color: vec3(desc.color[0], desc.color[1], desc.color[2]),
+            intensity: desc.intensity,
+            kind,
+        }
+    }
+}
+
+impl From<&Light> for LightDesc {
+    fn from(light: &Light) -> LightDesc {
+        let kind = match light.kind {
+            LightKind::Point => LightKindDesc::Point,
+            LightKind::Directional { direction } => LightKindDesc::Directional {
+                direction: vec2_to_desc(direction),
+            },
+            LightKind::Spot {
+                direction,
+                inner_angle,
+                outer_angle,
+            } => LightKindDesc::Spot {
+                direction: vec2_to_desc(direction),
+                inner_angle,
+                outer_angle,
+            },
+        };
+        LightDesc {
+            position: vec2_to_desc(light.position),
+            radius: light.radius,
+            color: [light.color.x(), light.color.y(), light.color.z()],
+            intensity: light.intensity,
+            kind,
+        }
+    }
+}
+
+impl From<&SdfShapeDesc> for SdfShape {
+    fn from(desc: &SdfShapeDesc) -> SdfShape {
+        let primitive = match &desc.primitive {
+            SdfPrimitiveDesc::Circle { radius } => SdfPrimitive::Circle { radius: *radius },
+            SdfPrimitiveDesc::Box { half_size } => SdfPrimitive::Box {
+                half_size: vec2_from_desc(*half_size),
+            },
+            SdfPrimitiveDesc::RoundedBox { half_size, radius } => SdfPrimitive::RoundedBox {
+                half_size: vec2_from_desc(*half_size),
+                radius: *radius,
+            },
+            SdfPrimitiveDesc::Segment { a, b, radius } => SdfPrimitive::Segment {
+                a: vec2_from_desc(*a),
+                b: vec2_from_desc(*b),
+                radius: *radius,
+            },
+            SdfPrimitiveDesc::ConvexPolygon { points } => SdfPrimitive::ConvexPolygon {
+                points: points.iter().map(|p| vec2_from_desc(*p)).collect(),
+            },
+            SdfPrimitiveDesc::EquilateralTriangle { half_side } => {
+                SdfPrimitive::EquilateralTriangle {
+                    half_side: *half_side,
+                }
+            }
+        };
+        let op = match desc.op {
+            SdfOpDesc::Union => SdfOp::Union,
+            SdfOpDesc::Intersection => SdfOp::Intersection,
+            SdfOpDesc::Subtraction => SdfOp::Subtraction,
+            SdfOpDesc::SmoothUnion { k } => SdfOp::SmoothUnion { k },
+        };
+        SdfShape {
+            primitive,
+            transform: Transform2D::new(
+                vec2_from_desc(desc.position),
+                desc.angle,
+                vec2_from_desc(desc.scale),
+            ),
+            color: vec3(desc.color[0], desc.color[1], desc.color[2]),
+            op,
+            emission: desc.emission,
+        }
+    }
+}
+
+impl From<&SdfShape> for SdfShapeDesc {
+    fn from(shape: &SdfShape) -> SdfShapeDesc {
+        let primitive = match &shape.primitive {
+            SdfPrimitive::Circle { radius } => SdfPrimitiveDesc::Circle { radius: *radius },
+            SdfPrimitive::Box { half_size } => SdfPrimitiveDesc::Box {
+                half_size: vec2_to_desc(*half_size),
+            },
+            SdfPrimitive::RoundedBox { half_size, radius } => SdfPrimitiveDesc::RoundedBox {
+                half_size: vec2_to_desc(*half_size),
+                radius: *radius,
+            },
+            SdfPrimitive::Segment { a, b, radius } => SdfPrimitiveDesc::Segment {
+                a: vec2_to_desc(*a),
+                b: vec2_to_desc(*b),
+                radius: *radius,
+            },
+            SdfPrimitive::ConvexPolygon { points } => SdfPrimitiveDesc::ConvexPolygon {
+                points: points.iter().map(|p| vec2_to_desc(*p)).collect(),
+            },
+            SdfPrimitive::EquilateralTriangle { half_side } => {
+                SdfPrimitiveDesc::EquilateralTriangle {
+                    half_side: *half_side,
+                }
+            }
+        };
+        let op = match shape.op {
+            SdfOp::Union => SdfOpDesc::Union,
+            SdfOp::Intersection => SdfOpDesc::Intersection,
+            SdfOp::Subtraction => SdfOpDesc::Subtraction,
+            SdfOp::SmoothUnion { k } => SdfOpDesc::SmoothUnion { k },
+        };
+        let transform = &shape.transform;
+        SdfShapeDesc {
+            position: vec2_to_desc(transform.translation),
+            angle: transform.rotation,
+            scale: vec2_to_desc(transform.scale),
+            color: [shape.color.x(), shape.color.y(), shape.color.z()],
+            primitive,
+            op,
+            emission: shape.emission,
+        }
+    }
+}
+
+#[test]
+fn default_scene_loads() {
+    let scene = SceneDesc::default_scene();
+    assert!(!scene.occluders.is_empty());
+    assert!(!scene.lights.is_empty());
+}
+
+#[test]
+fn default_scene_inside_bounds() {
+    let world = World::from_scene(&SceneDesc::default_scene());
+    let (min, max) = world.bounds;
+    let inside = |p: Vec2| p.cmpge(min).all() && p.cmple(max).all();
+    for occluder in world.occluders.iter() {
+        let polygon = occluder.placed_polygon();
+        assert!(polygon
+            .exterior()
+            .points_iter()
+            .all(|p| inside(vec2(p.x(), p.y()))));
+    }
+    for light in world.lights.iter() {
+        if let LightKind::Directional { .. } = light.kind {
+            continue;
+        }
+        assert!(inside(light.position));
+    }
+}
+
+#[test]
+fn scene_round_trip() {
+    let scene = SceneDesc {
+        ambient: [0.1, 0.2, 0.3],
+        occluders: vec![OccluderDesc {
+            exterior: vec![[-1., -1.], [1., -1.], [1., 1.], [-1., 1.]],
+            holes: vec![vec![[-0.5, -0.5], [0.5, -0.5], [0.5, 0.5], [-0.5, 0.5]]],
+            position: [0.3, -0.7],
+            angle: 0.5,
+            scale: [2., -1.],
+            velocity: [0., 0.01],
+            angular_velocity: -1.,
+            fixed: true,
+            emission: [1., 0.5, 0.],
+        }],
+        lights: vec![
+            LightDesc {
+                position: [0.1, 0.2],
+                radius: 0.5,
+                color: [1., 0.5, 0.25],
+                intensity: 0.7,
+                kind: LightKindDesc::Point,
+            },
+            LightDesc {
+                position: [0., 0.],
+                radius: 0.,
+                color: [1., 1., 1.],
+                intensity: 1.,
+                kind: LightKindDesc::Directional {
+                    direction: [1., -1.],
+                },
+            },
+            LightDesc {
+                position: [0., 3.],
+                radius: 0.1,
+                color: [1., 1., 1.],
+                intensity: 1.,
+                kind: LightKindDesc::Spot {
+                    direction: [0., -1.],
+                    inner_angle: 0.3,
+                    outer_angle: 0.6,
+                },
+            },
+        ],
+        textures: vec![TextureQuadDesc {
+            position: [1., 2.],
+            size: 0.5,
+            tint: [1., 0.5, 0.5, 0.8],
+        }],
+        sdf_shapes: vec![SdfShapeDesc {
+            position: [-1., 0.],
+            angle: 0.3,
+            scale: [1., 2.],
+            color: [0.5, 0.5, 1.],
+            primitive: SdfPrimitiveDesc::Segment {
+                a: [0., 0.],
+                b: [1., 1.],
+                radius: 0.1,
+            },
+            op: SdfOpDesc::SmoothUnion { k: 0.2 },
+            emission: 2.,
+        }],
+        bounds: Some([[-2., -1.], [2., 1.]]),
+        gravity: Some([0., -1.]),
+    };
+    let json = scene.to_json().unwrap();
+    assert_eq!(SceneDesc::from_json(&json).unwrap(), scene);
+}
+
+#[test]
+fn occluder_round_trip() {
+    for desc in random_occluders(4).iter() {
+        let polygon = desc.polygon();
+        let position = vec2_from_desc(desc.position);
+        let velocity = vec2_from_desc(desc.velocity);
+        assert_eq!(
+            &OccluderDesc::from_polygon(&polygon, position, velocity),
+            desc
+        );
+    }
+}
+
+#[test]
+fn light_round_trip() {
+    for desc in SceneDesc::default_scene().lights.iter() {
+        assert_eq!(&LightDesc::from(&Light::from(desc)), desc);
+    }
+}
+
+#[test]
+fn minimal_scene() {
+    let scene = SceneDesc::from_json("{}").unwrap();
+    assert!(scene.occluders.is_empty());
+}
+
+#[test]
+fn occluder_scale_defaults_to_one() {
+    let json = r#"{"occluders": [{"exterior": [[0, 0], [1, 0], [0, 1]], "position": [0, 0]}]}"#;
+    let scene = SceneDesc::from_json(json).unwrap();
+    assert_eq!(scene.occluders[0].scale, [1., 1.]);
+}
+
+#[test]
+fn world_round_trip() {
+    let scene = SceneDesc::default_scene();
+    let saved = SceneDesc::from_world(&World::from_scene(&scene));
+    assert_eq!(saved.occluders, scene.occluders);
+    assert_eq!(saved.lights, scene.lights);
+    assert_eq!(saved.ambient, scene.ambient);
+    assert_eq!(saved.bounds, scene.bounds);
+    assert_eq!(saved.gravity, scene.gravity);
+}
+
+#[test]
+fn sdf_shape_round_trip() {
+    let json = r#"{"sdf_shapes": [{"position": [1, -1]}]}"#;
+    let old = SceneDesc::from_json(json).unwrap();
+    assert_eq!(
+        SdfShape::from(&old.sdf_shapes[0]).primitive,
+        SdfShape::default().primitive
+    );
+    for desc in SceneDesc::default_scene().sdf_shapes.iter() {
+        assert_eq!(&SdfShapeDesc::from(&SdfShape::from(desc)), desc);
+    }
+}
@@ -0,0 +1,653 @@
+use crate::geometry::segment_distance;
+use crate::transform::Transform2D;
+use glam::{vec2, vec3, Vec2, Vec3};
+use std::fmt::Write;
+
+/// Distance used for an empty scene, also in the shader
+const FAR: f32 = 1E10;
+/// Size of the `sdf_params` array of vec4 in `scene_glsl`
+pub const MAX_SDF_PARAMS: usize = 64;
+
+/// Signed distance primitive in model coords, negative inside
+#[derive(Clone, Debug, PartialEq)]
+pub enum SdfPrimitive {
+    Circle {
+        radius: f32,
+    },
+    Box {
+        half_size: Vec2,
+    },
+    RoundedBox {
+        half_size: Vec2,
+        radius: f32,
+    },
+    /// Capsule of given radius around the segment
+    Segment {
+        a: Vec2,
+        b: Vec2,
+        radius: f32,
+    },
+    /// Vertices in any order of winding
+    ConvexPolygon {
+        points: Vec<Vec2>,
+    },
+    /// Centered at the origin, one vertex points up
+    EquilateralTriangle {
+        half_side: f32,
+    },
+}
+
+/// How a shape is combined with the shapes before it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SdfOp {
+    Union,
+    Intersection,
+    /// Cut the shape out of the previous ones
+    Subtraction,
+    /// Union with a blend of size k
+    SmoothUnion {
+        k: f32,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SdfShape {
+    pub primitive: SdfPrimitive,
+    /// Non uniform scale makes the distance approximate
+    pub transform: Transform2D,
+    pub color: Vec3,
+    pub op: SdfOp,
+    /// Light emitted in GI mode is color * emission
+    pub emission: f32,
+}
+
+impl SdfPrimitive {
+    pub fn distance(&self, p: Vec2) -> f32 {
+        match self {
+            SdfPrimitive::Circle { radius } => p.length() - radius,
+            SdfPrimitive::Box { half_size } => box_distance(p, *half_size),
+            SdfPrimitive::RoundedBox { half_size, radius } => {
+                box_distance(p, *half_size - vec2(*radius, *radius)) - radius
+            }
+            SdfPrimitive::Segment { a, b, radius } => segment_distance(p, *a, *b) - radius,
+            // less than 3 points is not a polygon, nothing is inside
+            SdfPrimitive::ConvexPolygon { points } if points.len() < 3 => FAR,
+            SdfPrimitive::ConvexPolygon { points } => {
+                let points = counter_clockwise(points);
+                let mut d = FAR;
+                let mut inside = true;
+                for (i, a) in points.iter().enumerate() {
+                    let b = points[(i + 1) % points.len()];
+                    d = d.min(segment_distance(p, *a, b));
+                    inside = inside && cross(b - *a, p - *a) >= 0.;
+                }
+                if inside {
+                    -d
+                } else {
+                    d
+                }
+            }
+            SdfPrimitive::EquilateralTriangle { half_side } => {
+                // https://www.shadertoy.com/view/Xl2yDW
+                let k = 3f32.sqrt();
+                let mut p = vec2(p.x().abs() - half_side, p.y() + half_side / k);
+                if p.x() + k * p.y() > 0. {
+                    p = vec2(p.x() - k * p.y(), -k * p.x() - p.y()) / 2.;
+                }
+                *p.x_mut() -= p.x().max(-2. * half_side).min(0.);
+                -p.length() * p.y().signum()
+            }
+        }
+    }
+
+    /// Radius of the circle around the origin which contains the primitive
+    pub fn bounding_radius(&self) -> f32 {
+        match self {
+            SdfPrimitive::Circle { radius } => *radius,
+            SdfPrimitive::Box { half_size } | SdfPrimitive::RoundedBox { half_size, .. } => {
+                half_size.length()
+            }
+            SdfPrimitive::Segment { a, b, radius } => a.length().max(b.length()) + radius,
+            SdfPrimitive::ConvexPolygon { points } => {
+                points.iter().map(|p| p.length()).fold(0., f32::max)
+            }
+            SdfPrimitive::EquilateralTriangle { half_side } => 2. * half_side / 3f32.sqrt(),
+        }
+    }
+
+    /// Size (radius or half side) and points of the primitive packed for `scene_params`
+    fn params(&self) -> (f32, Vec<[f32; 4]>) {
+        match self {
+            SdfPrimitive::Circle { radius } => (*radius, vec![]),
+            SdfPrimitive::Box { half_size } => (0., vec![[half_size.x(), half_size.y(), 0., 0.]]),
+            SdfPrimitive::RoundedBox { half_size, radius } => {
+                let inner = *half_size - vec2(*radius, *radius);
+                (*radius, vec![[inner.x(), inner.y(), 0., 0.]])
+            }
+            SdfPrimitive::Segment { a, b, radius } => (*radius, vec![[a.x(), a.y(), b.x(), b.y()]]),
+            SdfPrimitive::ConvexPolygon { points } if points.len() < 3 => (0., vec![]),
+            SdfPrimitive::ConvexPolygon { points } => {
+                // two points in every vec4, the last one is repeated for odd count
+                let points = counter_clockwise(points);
+                let params = points
+                    .chunks(2)
+                    .map(|pair| {
+                        let (a, b) = (pair[0], pair[pair.len() - 1]);
+                        [a.x(), a.y(), b.x(), b.y()]
+                    })
+                    .collect();
+                (0., params)
+            }
+            SdfPrimitive::EquilateralTriangle { half_side } => (*half_side, vec![]),
+        }
+    }
+
+    /// GLSL statements which set `d2` to the distance at model point `q`
+    /// `size` is the GLSL expression of the size, points are read from `sdf_params[data]` on
+    fn glsl(&self, size: &str, data: usize) -> String {
+        let expression = match self {
+            SdfPrimitive::Circle { .. } => format!("length(q) - {}", size),
+            SdfPrimitive::Box { .. } => format!("sd_box(q, sdf_params[{}].xy)", data),
+            SdfPrimitive::RoundedBox { .. } => {
+                format!("sd_box(q, sdf_params[{}].xy) - {}", data, size)
+            }
+            SdfPrimitive::Segment { .. } => format!(
+                "sd_segment(q, sdf_params[{0}].xy, sdf_params[{0}].zw) - {1}",
+                data, size
+            ),
+            SdfPrimitive::ConvexPolygon { points } if points.len() < 3 => float(FAR),
+            SdfPrimitive::ConvexPolygon { points } => {
+                let point =
+                    |i: usize| format!("sdf_params[{}].{}", data + i / 2, ["xy", "zw"][i % 2]);
+                // edges are unrolled, the source depends only on the number of points
+                let mut src = format!("    d2 = {};\n    inside = true;\n", float(FAR));
+                for i in 0..points.len() {
+                    let (a, b) = (point(i), point((i + 1) % points.len()));
+                    src += &format!("    d2 = min(d2, sd_segment(q, {}, {}));\n", a, b);
+                    src += &format!("    inside = inside && sd_side(q, {}, {}) >= 0.0;\n", a, b);
+                }
+                src += "    d2 = inside ? -d2 : d2;\n";
+                return src;
+            }
+            SdfPrimitive::EquilateralTriangle { .. } => format!("sd_triangle(q, {})", size),
+        };
+        format!("    d2 = {};\n", expression)
+    }
+}
+
+impl SdfShape {
+    /// Distance from world point, scaled by the smallest scale of the transform
+    pub fn distance(&self, point: Vec2) -> f32 {
+        self.primitive.distance(self.transform.inverse_apply(point)) * self.min_scale()
+    }
+
+    /// (min, max) corners of the box around the shape in world coords
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        let scale = self.transform.scale;
+        let r = self.primitive.bounding_radius() * scale.x().abs().max(scale.y().abs());
+        let center = self.transform.translation;
+        (center - vec2(r, r), center + vec2(r, r))
+    }
+
+    fn min_scale(&self) -> f32 {
+        let scale = self.transform.scale;
+        scale.x().abs().min(scale.y().abs())
+    }
+}
+
+impl Default for SdfShape {
+    fn default() -> SdfShape {
+        SdfShape {
+            primitive: SdfPrimitive::EquilateralTriangle { half_side: 0.5 },
+            transform: Transform2D::default(),
+            color: vec3(1., 1., 1.),
+            op: SdfOp::Union,
+            emission: 0.,
+        }
+    }
+}
+
+/// Distance and color of all shapes combined in order, op of the first shape is ignored
+pub fn scene_distance(shapes: &[SdfShape], point: Vec2) -> (f32, Vec3) {
+    combine_shapes(shapes, point, vec3(1., 1., 1.), |shape| shape.color)
+}
+
+/// Distance and emitted light (color * emission) of all shapes combined like `scene_distance`
+pub fn scene_emission(shapes: &[SdfShape], point: Vec2) -> (f32, Vec3) {
+    combine_shapes(shapes, point, vec3(0., 0., 0.), |shape| {
+        shape.color * shape.emission
+    })
+}
+
+/// Combine distances by ops, `value` of a shape is picked or mixed the same way as the distance
+fn combine_shapes(
+    shapes: &[SdfShape],
+    point: Vec2,
+    empty: Vec3,
+    value: impl Fn(&SdfShape) -> Vec3,
+) -> (f32, Vec3) {
+    let mut d = FAR;
+    let mut color = empty;
+    for (i, shape) in shapes.iter().enumerate() {
+        let d2 = shape.distance(point);
+        let op = if i == 0 { SdfOp::Union } else { shape.op };
+        match op {
+            SdfOp::Union => {
+                if d2 < d {
+                    d = d2;
+                    color = value(shape);
+                }
+            }
+            SdfOp::Intersection => {
+                if d2 > d {
+                    d = d2;
+                    color = value(shape);
+                }
+            }
+            SdfOp::Subtraction => d = d.max(-d2),
+            SdfOp::SmoothUnion { k } => {
+                // https://iquilezles.org/articles/smin
+                let h = if k > 0. {
+                    (0.5 + 0.5 * (d2 - d) / k).clamp(0., 1.)
+                } else if d2 > d {
+                    // no blend, plain union
+                    1.
+                } else {
+                    0.
+                };
+                d = d2 + (d - d2) * h - k * h * (1. - h);
+                color = value(shape) + (color - value(shape)) * h;
+            }
+        }
+    }
+    (d, color)
+}
+
+/// Box around all shapes extended by the margin, None if there are no shapes
+pub fn scene_bounds(shapes: &[SdfShape], margin: f32) -> Option<(Vec2, Vec2)> {
+    let mut bounds = shapes.iter().map(SdfShape::bounds);
+    let first = bounds.next()?;
+    let (min, max) = bounds.fold(first, |(min, max), (a, b)| (min.min(a), max.max(b)));
+    let margin = vec2(margin, margin);
+    Some((min - margin, max + margin))
+}
+
+/// Largest blend size of smooth unions, smoothed shapes may grow by k/4
+pub fn max_smoothing(shapes: &[SdfShape]) -> f32 {
+    shapes
+        .iter()
+        .map(|shape| match shape.op {
+            SdfOp::SmoothUnion { k } => k,
+            _ => 0.,
+        })
+        .fold(0., f32::max)
+}
+
+/// Values of the shapes read by `scene_glsl`: per shape a vec4 of translation, cos and sin of rotation,
+/// a vec4 of scale, the smallest scale and blend size, a vec4 of color and size, then points of the primitive
+pub fn scene_params(shapes: &[SdfShape]) -> Vec<[f32; 4]> {
+    let mut params = vec![];
+    for shape in shapes.iter() {
+        let (translation, scale) = (shape.transform.translation, shape.transform.scale);
+        let (sin, cos) = shape.transform.rotation.sin_cos();
+        let k = match shape.op {
+            SdfOp::SmoothUnion { k } => k,
+            _ => 0.,
+        };
+        let (size, points) = shape.primitive.params();
+        let color = shape.color;
+        params.push([translation.x(), translation.y(), cos, sin]);
+        params.push([scale.x(), scale.y(), shape.min_scale(), k]);
+        params.push([color.x(), color.y(), color.z(), size]);
+        params.extend(points);
+    }
+    params
+}
+
+/// GLSL source of `float scene(vec2 p, out vec3 color)` which mirrors `scene_distance`
+/// Shapes are read from `uniform vec4 sdf_params[MAX_SDF_PARAMS]` filled by `scene_params`,
+/// so the source changes only with kinds of primitives, polygon sizes and ops
+/// Needs the functions of `SDF_GLSL_LIBRARY`
+pub fn scene_glsl(shapes: &[SdfShape]) -> String {
+    let mut src = String::new();
+    writeln!(src, "uniform vec4 sdf_params[{}];", MAX_SDF_PARAMS).unwrap();
+    writeln!(src, "float scene(vec2 p, out vec3 color) {{").unwrap();
+    writeln!(src, "    float d = {};", float(FAR)).unwrap();
+    writeln!(src, "    color = vec3(1.0, 1.0, 1.0);").unwrap();
+    writeln!(src, "    vec4 t;").unwrap();
+    writeln!(src, "    vec2 q;").unwrap();
+    writeln!(src, "    float d2;").unwrap();
+    writeln!(src, "    float k;").unwrap();
+    writeln!(src, "    float h;").unwrap();
+    writeln!(src, "    bool inside;").unwrap();
+    // first param of the shape
+    let mut i = 0;
+    for (n, shape) in shapes.iter().enumerate() {
+        writeln!(src, "    t = sdf_params[{}];", i).unwrap();
+        writeln!(src, "    q = p - t.xy;").unwrap();
+        writeln!(
+            src,
+            "    q = vec2(q.x * t.z + q.y * t.w, -q.x * t.w + q.y * t.z) / sdf_params[{}].xy;",
+            i + 1
+        )
+        .unwrap();
+        let size = format!("sdf_params[{}].w", i + 2);
+        src += &shape.primitive.glsl(&size, i + 3);
+        writeln!(src, "    d2 *= sdf_params[{}].z;", i + 1).unwrap();
+        let color = format!("sdf_params[{}].rgb", i + 2);
+        let op = if n == 0 { SdfOp::Union } else { shape.op };
+        match op {
+            SdfOp::Union => {
+                writeln!(src, "    if (d2 < d) {{ d = d2; color = {}; }}", color).unwrap()
+            }
+            SdfOp::Intersection => {
+                writeln!(src, "    if (d2 > d) {{ d = d2; color = {}; }}", color).unwrap()
+            }
+            SdfOp::Subtraction => writeln!(src, "    d = max(d, -d2);").unwrap(),
+            SdfOp::SmoothUnion { .. } => {
+                writeln!(src, "    k = sdf_params[{}].w;", i + 1).unwrap();
+                writeln!(src, "    if (k > 0.0) {{").unwrap();
+                writeln!(
+                    src,
+                    "        h = clamp(0.5 + 0.5 * (d2 - d) / k, 0.0, 1.0);"
+                )
+                .unwrap();
+                writeln!(src, "        d = mix(d2, d, h) - k * h * (1.0 - h);").unwrap();
+                writeln!(src, "        color = mix({}, color, h);", color).unwrap();
+                // no blend, plain union like in `combine_shapes`
+                writeln!(
+                    src,
+                    "    }} else if (d2 <= d) {{ d = d2; color = {}; }}",
+                    color
+                )
+                .unwrap();
+            }
+        }
+        i += 3 + shape.primitive.params().1.len();
+    }
+    writeln!(src, "    return d;").unwrap();
+    writeln!(src, "}}").unwrap();
+    src
+}
+
+/// Distance functions used by `scene_glsl`
+pub const SDF_GLSL_LIBRARY: &str = r#"
+    float sd_box(vec2 p, vec2 b) {
+        vec2 d = abs(p) - b;
+        return length(max(d, 0.0)) + min(max(d.x, d.y), 0.0);
+    }
+
+    float sd_segment(vec2 p, vec2 a, vec2 b) {
+        vec2 pa = p - a;
+        vec2 ba = b - a;
+        float h = clamp(dot(pa, ba) / dot(ba, ba), 0.0, 1.0);
+        return length(pa - ba * h);
+    }
+
+    // positive on the left of the edge (inside of counter clockwise polygon)
+    float sd_side(vec2 p, vec2 a, vec2 b) {
+        vec2 ba = b - a;
+        return ba.x * (p.y - a.y) - ba.y * (p.x - a.x);
+    }
+
+    // https://www.shadertoy.com/view/Xl2yDW
+    float sd_triangle(vec2 p, float r) {
+        const float k = 1.7320508;
+        p.x = abs(p.x) - r;
+        p.y = p.y + r / k;
+        if (p.x + k * p.y > 0.0) p = vec2(p.x - k * p.y, -k * p.x - p.y) / 2.0;
+        p.x -= clamp(p.x, -2.0 * r, 0.0);
+        return -length(p) * sign(p.y);
+    }
+"#;
+
+/// Winding of the polygon made counter clockwise
+fn counter_clockwise(points: &[Vec2]) -> Vec<Vec2> {
+    let area: f32 = points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| cross(*a, *b))
+        .sum();
+    let mut points = points.to_vec();
+    if area < 0. {
+        points.reverse();
+    }
+    points
+}
+
+fn box_distance(p: Vec2, half_size: Vec2) -> f32 {
+    let d = p.abs() - half_size;
+    d.max(vec2(0., 0.)).length() + d.x().max(d.y()).min(0.)
+}
+
+fn cross(a: Vec2, b: Vec2) -> f32 {
+    a.x() * b.y() - a.y() * b.x()
+}
+
+/// Float literal with decimal point
+fn float(x: f32) -> String {
+    format!("{:?}", x)
+}
+
+#[cfg(test)]
+fn near(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1E-5
+}
+
+#[test]
+fn primitive_distances() {
+    let p = vec2(2., 0.);
+    assert!(near(SdfPrimitive::Circle { radius: 0.5 }.distance(p), 1.5));
+    let half_size = vec2(1., 0.5);
+    assert!(near(SdfPrimitive::Box { half_size }.distance(p), 1.));
+    assert!(near(
+        SdfPrimitive::Box { half_size }.distance(vec2(0., 0.)),
+        -0.5
+    ));
+    let rounded = SdfPrimitive::RoundedBox {
+        half_size,
+        radius: 0.25,
+    };
+    assert!(near(rounded.distance(p), 1.));
+    // corner is rounded
+    let corner = vec2(2., 1.5);
+    assert!(near(
+        rounded.distance(corner),
+        (vec2(1., 1.) + vec2(0.25, 0.25)).length() - 0.25
+    ));
+    let segment = SdfPrimitive::Segment {
+        a: vec2(-1., 0.),
+        b: vec2(1., 0.),
+        radius: 0.1,
+    };
+    assert!(near(segment.distance(vec2(0., 1.)), 0.9));
+    assert!(near(segment.distance(p), 0.9));
+    let triangle = SdfPrimitive::EquilateralTriangle { half_side: 0.5 };
+    assert!(triangle.distance(vec2(0., 0.)) < 0.);
+    let top = 2. * 0.5 / 3f32.sqrt();
+    assert!(near(triangle.distance(vec2(0., top + 0.3)), 0.3));
+    assert!(near(triangle.bounding_radius(), top));
+}
+
+#[test]
+fn convex_polygon_matches_box() {
+    let square = [vec2(-1., -1.), vec2(1., -1.), vec2(1., 1.), vec2(-1., 1.)];
+    let box_sdf = SdfPrimitive::Box {
+        half_size: vec2(1., 1.),
+    };
+    for points in [square.to_vec(), square.iter().rev().cloned().collect()].iter() {
+        let polygon = SdfPrimitive::ConvexPolygon {
+            points: points.clone(),
+        };
+        for p in [vec2(0., 0.), vec2(0.5, -0.2), vec2(3., 0.), vec2(2., 2.)].iter() {
+            assert!(near(polygon.distance(*p), box_sdf.distance(*p)));
+        }
+    }
+}
+
+#[test]
+fn degenerate_polygon_is_empty() {
+    for points in [
+        vec![],
+        vec![vec2(0., 0.)],
+        vec![vec2(-1., 0.), vec2(1., 0.)],
+    ]
+    .iter()
+    {
+        let polygon = SdfPrimitive::ConvexPolygon {
+            points: points.clone(),
+        };
+        assert_eq!(polygon.distance(vec2(0., 0.)), FAR);
+        assert_eq!(
+            polygon.glsl("size", 0),
+            format!("    d2 = {};\n", float(FAR))
+        );
+        let shape = SdfShape {
+            primitive: polygon,
+            ..SdfShape::default()
+        };
+        assert!(scene_distance(&[shape], vec2(5., 5.)).0 > 0.);
+    }
+}
+
+#[test]
+fn transformed_shape() {
+    let shape = SdfShape {
+        primitive: SdfPrimitive::Box {
+            half_size: vec2(1., 0.5),
+        },
+        transform: Transform2D::new(vec2(3., 1.), std::f32::consts::FRAC_PI_2, vec2(2., 2.)),
+        ..SdfShape::default()
+    };
+    // rotated box is 2 wide and 4 tall
+    assert!(near(shape.distance(vec2(3., 4.)), 1.));
+    assert!(near(shape.distance(vec2(5., 1.)), 1.));
+    let (min, max) = shape.bounds();
+    assert!(min.x() < 2. && max.y() > 3.);
+}
+
+#[test]
+fn boolean_ops() {
+    let circle = |x: f32, op: SdfOp, color: Vec3| SdfShape {
+        primitive: SdfPrimitive::Circle { radius: 1. },
+        transform: Transform2D::from_translation(vec2(x, 0.)),
+        color,
+        op,
+        emission: 0.,
+    };
+    let red = vec3(1., 0., 0.);
+    let blue = vec3(0., 0., 1.);
+    let scene = |op| [circle(0., SdfOp::Union, red), circle(1.5, op, blue)];
+    let p = vec2(1.5, 0.);
+    let (d, color) = scene_distance(&scene(SdfOp::Union), p);
+    assert!(near(d, -1.) && color == blue);
+    let (d, _) = scene_distance(&scene(SdfOp::Intersection), vec2(0.75, 0.));
+    assert!(near(d, -0.25));
+    assert!(scene_distance(&scene(SdfOp::Intersection), p).0 > 0.);
+    let (d, color) = scene_distance(&scene(SdfOp::Subtraction), vec2(-0.5, 0.));
+    assert!(near(d, -0.5) && color == red);
+    assert!(scene_distance(&scene(SdfOp::Subtraction), vec2(0.75, 0.)).0 > 0.);
+    // smooth union is below the plain union between the shapes and mixes colors
+    let (smooth, color) = scene_distance(&scene(SdfOp::SmoothUnion { k: 0.5 }), vec2(0.75, 0.));
+    assert!(smooth < -0.25);
+    assert!(color.x() > 0. && color.z() > 0.);
+    assert_eq!(scene_distance(&[], p).0, FAR);
+}
+
+#[test]
+fn glsl_has_every_shape() {
+    let shapes = vec![
+        SdfShape::default(),
+        SdfShape {
+            primitive: SdfPrimitive::ConvexPolygon {
+                points: vec![vec2(0., 0.), vec2(1., 0.), vec2(0., 1.)],
+            },
+            op: SdfOp::SmoothUnion { k: 0.1 },
+            ..SdfShape::default()
+        },
+        SdfShape {
+            primitive: SdfPrimitive::Circle { radius: 0.2 },
+            op: SdfOp::Subtraction,
+            ..SdfShape::default()
+        },
+    ];
+    let src = scene_glsl(&shapes);
+    assert_eq!(src.matches("sd_triangle(").count(), 1);
+    assert_eq!(src.matches("sd_side(").count(), 3);
+    assert_eq!(src.matches("max(d, -d2)").count(), 1);
+    assert_eq!(src.matches('{').count(), src.matches('}').count());
+    assert!(src.ends_with("return d;\n}\n"));
+    let bounds = scene_bounds(&shapes, 0.1).unwrap();
+    assert!(bounds.0.x() <= -1.1 && bounds.1.x() >= 1.1);
+    assert_eq!(scene_bounds(&[], 0.1), None);
+}
+
+#[test]
+fn glsl_depends_only_on_kinds_and_ops() {
+    let shapes = |x: f32, k: f32| {
+        vec![
+            SdfShape {
+                transform: Transform2D::from_translation(vec2(x, 0.)),
+                ..SdfShape::default()
+            },
+            SdfShape {
+                primitive: SdfPrimitive::ConvexPolygon {
+                    points: vec![vec2(0., 0.), vec2(x, 0.), vec2(0., 1.)],
+                },
+                color: vec3(x, 0., 0.),
+                op: SdfOp::SmoothUnion { k },
+                ..SdfShape::default()
+            },
+            SdfShape {
+                primitive: SdfPrimitive::Segment {
+                    a: vec2(0., 0.),
+                    b: vec2(x, 1.),
+                    radius: 0.1,
+                },
+                ..SdfShape::default()
+            },
+        ]
+    };
+    let src = scene_glsl(&shapes(1., 0.1));
+    assert_eq!(src, scene_glsl(&shapes(2., 0.3)));
+    assert_ne!(
+        scene_params(&shapes(1., 0.1)),
+        scene_params(&shapes(2., 0.3))
+    );
+    let mut other = shapes(1., 0.1);
+    other[2].op = SdfOp::Subtraction;
+    assert_ne!(src, scene_glsl(&other));
+    // 3 vec4 per shape, 2 for polygon points and 1 for the segment
+    let params = scene_params(&shapes(1., 0.1));
+    assert_eq!(params.len(), 3 * 3 + 2 + 1);
+    assert_eq!(params[4][3], 0.1);
+    let last = format!("sdf_params[{}]", params.len() - 1);
+    let after = format!("sdf_params[{}]", params.len());
+    assert!(src.contains(&last) && !src.contains(&after));
+}
+
+#[test]
+fn smooth_union_without_blend() {
+    let circle = |x: f32, op: SdfOp| SdfShape {
+        primitive: SdfPrimitive::Circle { radius: 1. },
+        transform: Transform2D::from_translation(vec2(x, 0.)),
+        op,
+        ..SdfShape::default()
+    };
+    let smooth = [
+        circle(0., SdfOp::Union),
+        circle(1.5, SdfOp::SmoothUnion { k: 0. }),
+    ];
+    let plain = [circle(0., SdfOp::Union), circle(1.5, SdfOp::Union)];
+    for p in [vec2(0.75, 0.), vec2(1.5, 0.), vec2(-3., 1.)].iter() {
+        let d = scene_distance(&smooth, *p).0;
+        assert!(d.is_finite() && near(d, scene_distance(&plain, *p).0));
+    }
+    // the shader divides by k only when it is positive
+    let src = scene_glsl(&smooth);
+    let lines: Vec<_> = src.lines().map(str::trim).collect();
+    let division = lines.iter().position(|l| l.contains("/ k")).unwrap();
+    assert_eq!(lines[division - 1], "if (k > 0.0) {");
+    assert_eq!(src.matches("/ k").count(), 1);
+    assert!(src.contains("} else if (d2 <= d) { d = d2; color = sdf_params[5].rgb; }"));
+}
@@ -0,0 +1,313 @@
+use crate::geometry::projective_textures;
+use crate::normal_map::normal_map_from_brightness;
+use glam::{vec2, vec4, Vec2, Vec3, Vec4};
+
+/// Index of an image added to `TextureRenderer`
+pub type TextureId = usize;
+
+/// Max number of vertices in one vertex buffer, indices are u16
+pub const MAX_BATCH_VERTICES: usize = u16::MAX as usize;
+
+/// RGBA image decoded at runtime
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// Rows go from the top of the image, 4 bytes per pixel
+    pub rgba: Vec<u8>,
+}
+
+impl Image {
+    /// Decode png bytes (read from a file, fetched or `include_bytes!`)
+    /// Palette, grayscale and 16 bit images are converted to 8 bit RGBA
+    pub fn from_png(bytes: &[u8]) -> Result<Image, png::DecodingError> {
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let (info, mut reader) = decoder.read_info()?;
+        let mut data = vec![0; info.buffer_size()];
+        reader.next_frame(&mut data)?;
+        let rgba = match info.color_type {
+            png::ColorType::RGBA => data,
+            png::ColorType::RGB => data
+                .chunks(3)
+                .flat_map(|p| vec![p[0], p[1], p[2], 255])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => data
+                .chunks(2)
+                .flat_map(|p| vec![p[0], p[0], p[0], p[1]])
+                .collect(),
+            png::ColorType::Grayscale => data.iter().flat_map(|v| vec![*v, *v, *v, 255]).collect(),
+            png::ColorType::Indexed => {
+                return Err(png::DecodingError::Other("palette is not expanded".into()))
+            }
+        };
+        Ok(Image {
+            width: info.width,
+            height: info.height,
+            rgba,
+        })
+    }
+
+    /// Normal map made from brightness of the image, see `normal_map_from_brightness`
+    pub fn normal_map(&self, strength: f32) -> Image {
+        Image {
+            width: self.width,
+            height: self.height,
+            rgba: normal_map_from_brightness(
+                &self.rgba,
+                self.width as usize,
+                self.height as usize,
+                strength,
+            ),
+        }
+    }
+}
+
+/// Textured quad with arbitrary corners, the texture is interpolated projectively
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprite {
+    pub texture: TextureId,
+    /// World positions of bottom left, bottom right, top right and top left corners of the region
+    pub corners: [Vec2; 4],
+    /// Texture coords of the top left and the bottom right of the image part, swap them to mirror it
+    pub region: (Vec2, Vec2),
+    /// Texture color is multiplied by it
+    pub tint: Vec4,
+}
+
+impl Sprite {
+    /// Whole image without tint
+    pub fn new(texture: TextureId, corners: [Vec2; 4]) -> Sprite {
+        Sprite {
+            texture,
+            corners,
+            region: (vec2(0., 0.), vec2(1., 1.)),
+            tint: vec4(1., 1., 1., 1.),
+        }
+    }
+
+    /// Axis aligned sprite
+    pub fn rect(texture: TextureId, center: Vec2, half_size: Vec2) -> Sprite {
+        let (x, y) = (half_size.x(), half_size.y());
+        Sprite::new(
+            texture,
+            [
+                center + vec2(-x, -y),
+                center + vec2(x, -y),
+                center + vec2(x, y),
+                center + vec2(-x, y),
+            ],
+        )
+    }
+
+    /// Vertices in the order of corners
+    pub fn vertices(&self) -> [SpriteVertex; 4] {
+        let c = &self.corners;
+        let (min, max) = self.region;
+        let uv = [
+            vec2(min.x(), max.y()),
+            vec2(max.x(), max.y()),
+            vec2(max.x(), min.y()),
+            vec2(min.x(), min.y()),
+        ];
+        let uvq = projective_textures(c, &uv);
+        // world directions of growing u and v, v grows from the top of the image
+        let tangent = ((c[1] - c[0]) + (c[2] - c[3])) * (max.x() - min.x()).signum();
+        let bitangent = ((c[0] - c[3]) + (c[1] - c[2])) * (max.y() - min.y()).signum();
+        let (tangent, bitangent) = (tangent.normalize(), bitangent.normalize());
+        let tint = [self.tint.x(), self.tint.y(), self.tint.z(), self.tint.w()];
+        let mut vertices = [SpriteVertex {
+            pos: c[0],
+            uv: uvq[0],
+            tangent,
+            bitangent,
+            tint,
+        }; 4];
+        for i in 1..4 {
+            vertices[i].pos = c[i];
+            vertices[i].uv = uvq[i];
+        }
+        vertices
+    }
+}
+
+/// Vertex of sprite batches
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpriteVertex {
+    pub pos: Vec2,
+    /// Texture coords multiplied by the homogeneous coordinate
+    pub uv: Vec3,
+    /// World directions of growing u and v, normals of normal maps are turned by them
+    pub tangent: Vec2,
+    pub bitangent: Vec2,
+    /// Not `Vec4`, it may be 16 byte aligned and vertices have to be packed
+    pub tint: [f32; 4],
+}
+
+/// Sprites of one texture, a range of the index buffer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Batch {
+    pub texture: TextureId,
+    /// First index and number of indices
+    pub start: usize,
+    pub count: usize,
+}
+
+/// Sprites which fit into one vertex buffer (up to `MAX_BATCH_VERTICES` vertices)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpriteChunk {
+    pub vertices: Vec<SpriteVertex>,
+    /// Indices of the chunk's own vertices
+    pub indices: Vec<u16>,
+    pub batches: Vec<Batch>,
+}
+
+/// Vertices and indices of all sprites sorted by texture, one batch per texture in every chunk
+/// Sprites of the same texture keep their order, so later ones are drawn on top
+pub fn batch_sprites(sprites: &[Sprite]) -> Vec<SpriteChunk> {
+    let mut order: Vec<_> = (0..sprites.len()).collect();
+    order.sort_by_key(|i| sprites[*i].texture);
+    let mut chunks: Vec<SpriteChunk> = vec![];
+    for i in order {
+        let sprite = &sprites[i];
+        match chunks.last() {
+            Some(chunk) if chunk.vertices.len() + 4 <= MAX_BATCH_VERTICES => (),
+            _ => chunks.push(SpriteChunk::default()),
+        }
+        let chunk = chunks.last_mut().unwrap();
+        match chunk.batches.last_mut() {
+            Some(batch) if batch.texture == sprite.texture => batch.count += 6,
+            _ => chunk.batches.push(Batch {
+                texture: sprite.texture,
+                start: chunk.indices.len(),
+                count: 6,
+            }),
+        }
+        let shift = chunk.vertices.len() as u16;
+        chunk
+            .indices
+            .extend([0, 1, 2, 3, 2, 0].iter().map(|i| shift + i));
+        chunk.vertices.extend_from_slice(&sprite.vertices());
+    }
+    chunks
+}
+
+#[cfg(test)]
+fn encoded_png(color: png::ColorType, width: u32, height: u32, data: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![];
+    {
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(color);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(data).unwrap();
+    }
+    bytes
+}
+
+#[test]
+fn decode_png() {
+    let rgba = [10, 20, 30, 40, 50, 60, 70, 80];
+    let image = Image::from_png(&encoded_png(png::ColorType::RGBA, 2, 1, &rgba)).unwrap();
+    assert_eq!((image.width, image.height), (2, 1));
+    assert_eq!(image.rgba, rgba.to_vec());
+    let rgb =
+        Image::from_png(&encoded_png(png::ColorType::RGB, 1, 2, &[1, 2, 3, 4, 5, 6])).unwrap();
+    assert_eq!(rgb.rgba, vec![1, 2, 3, 255, 4, 5, 6, 255]);
+    let gray = Image::from_png(&encoded_png(png::ColorType::Grayscale, 1, 1, &[7])).unwrap();
+    assert_eq!(gray.rgba, vec![7, 7, 7, 255]);
+    assert!(Image::from_png(b"not a png").is_err());
+    let robot = Image::from_png(include_bytes!("../vintage-robot.png")).unwrap();
+    assert_eq!(robot.rgba.len(), (robot.width * robot.height * 4) as usize);
+}
+
+#[test]
+fn sprite_vertices() {
+    let mut sprite = Sprite::rect(0, vec2(1., 1.), vec2(1., 0.5));
+    sprite.region = (vec2(0.5, 0.), vec2(1., 0.25));
+    sprite.tint = vec4(1., 0., 0., 0.5);
+    let vertices = sprite.vertices();
+    let uv: Vec<_> = vertices
+        .iter()
+        .map(|v| vec2(v.uv.x() / v.uv.z(), v.uv.y() / v.uv.z()))
+        .collect();
+    // bottom of the sprite is the bottom of the region
+    assert_eq!(
+        uv,
+        vec![vec2(0.5, 0.25), vec2(1., 0.25), vec2(1., 0.), vec2(0.5, 0.)]
+    );
+    assert_eq!(vertices[2].pos, vec2(2., 1.5));
+    assert!(vertices.iter().all(|v| v.tint == [1., 0., 0., 0.5]));
+    assert_eq!(vertices[0].tangent, vec2(1., 0.));
+    assert_eq!(vertices[0].bitangent, vec2(0., -1.));
+    // mirrored region turns the tangent
+    sprite.region = (vec2(1., 0.), vec2(0., 1.));
+    assert_eq!(sprite.vertices()[0].tangent, vec2(-1., 0.));
+    assert_eq!(std::mem::size_of::<SpriteVertex>(), 13 * 4);
+}
+
+#[test]
+fn deformed_sprite() {
+    // trapezoid keeps the texture straight along the sides
+    let sprite = Sprite::new(
+        0,
+        [vec2(-2., 0.), vec2(2., 0.), vec2(1., 1.), vec2(-1., 1.)],
+    );
+    let vertices = sprite.vertices();
+    assert!(vertices[0].uv.z() != vertices[2].uv.z());
+    for (v, corner) in vertices
+        .iter()
+        .zip(&[(0., 1.), (1., 1.), (1., 0.), (0., 0.)])
+    {
+        assert!((v.uv.x() / v.uv.z() - corner.0).abs() < 1E-6);
+        assert!((v.uv.y() / v.uv.z() - corner.1).abs() < 1E-6);
+    }
+}
+
+#[test]
+fn batches_by_texture() {
+    let sprite = |texture, x| Sprite::rect(texture, vec2(x, 0.), vec2(0.5, 0.5));
+    let sprites = [sprite(1, 0.), sprite(0, 1.), sprite(1, 2.), sprite(2, 3.)];
+    let chunks = batch_sprites(&sprites);
+    assert_eq!(chunks.len(), 1);
+    let SpriteChunk {
+        vertices,
+        indices,
+        batches,
+    } = &chunks[0];
+    assert_eq!(vertices.len(), 16);
+    assert_eq!(indices.len(), 24);
+    let textures: Vec<_> = batches
+        .iter()
+        .map(|b| (b.texture, b.start, b.count))
+        .collect();
+    assert_eq!(textures, vec![(0, 0, 6), (1, 6, 12), (2, 18, 6)]);
+    // sprites of one texture keep their order
+    assert_eq!(vertices[4].pos, sprites[0].corners[0]);
+    assert_eq!(vertices[8].pos, sprites[2].corners[0]);
+    assert_eq!(&indices[6..12], &[4, 5, 6, 7, 6, 4]);
+    assert_eq!(batch_sprites(&[]), vec![]);
+}
+
+#[test]
+fn batches_split_by_index_range() {
+    let sprites: Vec<_> = (0..MAX_BATCH_VERTICES / 4 + 10)
+        .map(|i| Sprite::rect(i % 2, vec2(i as f32, 0.), vec2(0.5, 0.5)))
+        .collect();
+    let chunks = batch_sprites(&sprites);
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[1].vertices.len(), 40);
+    for chunk in chunks.iter() {
+        assert!(chunk.vertices.len() <= MAX_BATCH_VERTICES);
+        let max = *chunk.indices.iter().max().unwrap() as usize;
+        assert_eq!(max, chunk.vertices.len() - 1);
+        let count: usize = chunk.batches.iter().map(|b| b.count).sum();
+        assert_eq!(count, chunk.indices.len());
+    }
+    // texture 1 continues in the second chunk
+    assert_eq!(chunks[0].batches.len(), 2);
+    assert_eq!(chunks[1].batches.len(), 1);
+    assert_eq!(chunks[1].batches[0].texture, 1);
+    assert_eq!(chunks[1].indices[..6], [0, 1, 2, 3, 2, 0]);
+}
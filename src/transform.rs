@@ -0,0 +1,104 @@
+use geo::{LineString, Polygon};
+use glam::{vec2, Vec2};
+
+/// Model to world transform: scale, then counter clockwise rotation, then translation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform2D {
+    pub translation: Vec2,
+    /// Radians
+    pub rotation: f32,
+    /// Negative scale mirrors the model
+    pub scale: Vec2,
+}
+
+impl Default for Transform2D {
+    fn default() -> Transform2D {
+        Transform2D::from_translation(vec2(0., 0.))
+    }
+}
+
+impl Transform2D {
+    pub fn new(translation: Vec2, rotation: f32, scale: Vec2) -> Transform2D {
+        Transform2D {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    pub fn from_translation(translation: Vec2) -> Transform2D {
+        Transform2D::new(translation, 0., vec2(1., 1.))
+    }
+
+    /// Model point to world coords
+    pub fn apply(&self, point: Vec2) -> Vec2 {
+        self.apply_linear(point) + self.translation
+    }
+
+    /// Model vector to world coords, translation is not applied
+    pub fn apply_linear(&self, vector: Vec2) -> Vec2 {
+        let scaled = vector * self.scale;
+        let (sin, cos) = self.rotation.sin_cos();
+        vec2(
+            scaled.x() * cos - scaled.y() * sin,
+            scaled.x() * sin + scaled.y() * cos,
+        )
+    }
+
+    /// World point to model coords (used for picking)
+    /// Zero scale can't be inverted, the result is not finite then
+    pub fn inverse_apply(&self, point: Vec2) -> Vec2 {
+        let local = point - self.translation;
+        let (sin, cos) = self.rotation.sin_cos();
+        let unrotated = vec2(
+            local.x() * cos + local.y() * sin,
+            -local.x() * sin + local.y() * cos,
+        );
+        unrotated / self.scale
+    }
+
+    /// Polygon in world coords
+    pub fn apply_polygon(&self, polygon: &Polygon<f32>) -> Polygon<f32> {
+        let transform = |ring: &LineString<f32>| -> LineString<f32> {
+            ring.points_iter()
+                .map(|p| {
+                    let p = self.apply(vec2(p.x(), p.y()));
+                    (p.x(), p.y())
+                })
+                .collect::<Vec<_>>()
+                .into()
+        };
+        Polygon::new(
+            transform(polygon.exterior()),
+            polygon.interiors().iter().map(transform).collect(),
+        )
+    }
+}
+
+#[test]
+fn transform_order() {
+    let transform = Transform2D::new(vec2(1., 2.), std::f32::consts::FRAC_PI_2, vec2(2., 3.));
+    // scaled to (2, 0), rotated to (0, 2), moved to (1, 4)
+    assert!((transform.apply(vec2(1., 0.)) - vec2(1., 4.)).length() < 1E-6);
+    assert!((transform.apply_linear(vec2(0., 1.)) - vec2(-3., 0.)).length() < 1E-6);
+}
+
+#[test]
+fn inverse_apply_round_trip() {
+    let transform = Transform2D::new(vec2(-0.5, 3.), 0.7, vec2(-1.5, 0.4));
+    for p in [vec2(0., 0.), vec2(1., -2.), vec2(-3., 0.25)].iter() {
+        let back = transform.inverse_apply(transform.apply(*p));
+        assert!((back - *p).length() < 1E-5);
+    }
+}
+
+#[test]
+fn rotate_polygon_quarter() {
+    let polygon = Polygon::new(vec![(1., 0.), (2., 0.), (2., 1.)].into(), vec![]);
+    let rotated = Transform2D::new(vec2(0., 0.), std::f32::consts::FRAC_PI_2, vec2(1., 1.))
+        .apply_polygon(&polygon);
+    let expected = [(0., 1.), (0., 2.), (-1., 2.), (0., 1.)];
+    for (p, e) in rotated.exterior().points_iter().zip(expected.iter()) {
+        assert!((p.x() - e.0).abs() < 1E-6 && (p.y() - e.1).abs() < 1E-6);
+    }
+}
@@ -0,0 +1,291 @@
+use crate::geometry::{polygon_contains, visibility_polygon, ShadowPoint};
+use crate::light::Light;
+use crate::physics::{self, PhysicsParams};
+use crate::scene::{vec2_from_desc, OccluderDesc, SceneDesc};
+use crate::transform::Transform2D;
+use geo::Polygon;
+use glam::{vec2, vec3, Vec2, Vec3};
+
+/// Default half size of the world box, limits visibility rays
+pub const WORLD_HALF_SIZE: f32 = 5.;
+
+/// Shadow casting polygon placed in the world
+#[derive(Clone, Debug)]
+pub struct Occluder {
+    /// Model coords
+    pub polygon: Polygon<f32>,
+    /// Rotates about the model origin, physics integrates spin about the centroid
+    /// and compensates the translation for it
+    pub transform: Transform2D,
+    pub velocity: Vec2,
+    pub angular_velocity: f32,
+    /// Fixed occluders are not moved by physics
+    pub fixed: bool,
+    /// Light emitted in GI mode, black -- only blocks and bounces light
+    pub emission: Vec3,
+}
+
+impl Occluder {
+    /// Fixed occluder at the translation without rotation, scale and emission
+    pub fn fixed(polygon: Polygon<f32>, translation: Vec2) -> Occluder {
+        Occluder {
+            polygon,
+            transform: Transform2D::from_translation(translation),
+            velocity: vec2(0., 0.),
+            angular_velocity: 0.,
+            fixed: true,
+            emission: Vec3::zero(),
+        }
+    }
+
+    /// Polygon in world coords
+    pub fn placed_polygon(&self) -> Polygon<f32> {
+        self.transform.apply_polygon(&self.polygon)
+    }
+
+    /// World point is inside the polygon
+    pub fn contains(&self, point: Vec2) -> bool {
+        polygon_contains(&self.polygon, self.transform.inverse_apply(point))
+    }
+}
+
+impl From<&OccluderDesc> for Occluder {
+    fn from(desc: &OccluderDesc) -> Occluder {
+        Occluder {
+            polygon: desc.polygon(),
+            transform: Transform2D::new(
+                vec2_from_desc(desc.position),
+                desc.angle,
+                vec2_from_desc(desc.scale),
+            ),
+            velocity: vec2_from_desc(desc.velocity),
+            angular_velocity: desc.angular_velocity,
+            fixed: desc.fixed,
+            emission: vec3(desc.emission[0], desc.emission[1], desc.emission[2]),
+        }
+    }
+}
+
+/// Occluders, lights and shadow geometry without any GL state
+/// Renderers only read it, so the whole update can run headless
+pub struct World {
+    pub occluders: Vec<Occluder>,
+    pub lights: Vec<Light>,
+    /// Light which is everywhere, even in shadows
+    pub ambient: Vec3,
+    /// (min, max) corners of the world box, occluders bounce off its walls
+    pub bounds: (Vec2, Vec2),
+    pub physics: PhysicsParams,
+    /// Shadow geometry(triangle list) of each light, filled by `compute_shadows`
+    shadows: Vec<Vec<ShadowPoint>>,
+    /// Mass properties of occluders, kept between updates
+    bodies: physics::Bodies,
+}
+
+impl Default for World {
+    fn default() -> World {
+        World {
+            occluders: vec![],
+            lights: vec![],
+            ambient: vec3(0.1, 0.1, 0.1),
+            bounds: (
+                vec2(-WORLD_HALF_SIZE, -WORLD_HALF_SIZE),
+                vec2(WORLD_HALF_SIZE, WORLD_HALF_SIZE),
+            ),
+            physics: PhysicsParams::default(),
+            shadows: vec![],
+            bodies: physics::Bodies::default(),
+        }
+    }
+}
+
+impl World {
+    pub fn from_scene(scene: &SceneDesc) -> World {
+        let ambient = scene.ambient;
+        let mut world = World {
+            occluders: scene.occluders.iter().map(Occluder::from).collect(),
+            lights: scene.lights.iter().map(Light::from).collect(),
+            ambient: vec3(ambient[0], ambient[1], ambient[2]),
+            ..World::default()
+        };
+        if let Some([min, max]) = scene.bounds {
+            world.bounds = (vec2_from_desc(min), vec2_from_desc(max));
+        }
+        if let Some(gravity) = scene.gravity {
+            world.physics.gravity = vec2_from_desc(gravity);
+        }
+        world
+    }
+
+    /// Simulate physics of occluders for dt seconds
+    pub fn update(&mut self, dt: f32) {
+        physics::step(
+            &mut self.occluders,
+            &mut self.bodies,
+            self.bounds,
+            &self.physics,
+            dt,
+        );
+    }
+
+    /// Recompute shadow geometry of every light
+    pub fn compute_shadows(&mut self) {
+        self.shadows.resize(self.lights.len(), vec![]);
+        for shadows in self.shadows.iter_mut() {
+            shadows.clear();
+        }
+        for occluder in self.occluders.iter() {
+            for (light, shadows) in self.lights.iter().zip(self.shadows.iter_mut()) {
+                shadows.extend(light.shadow_volume(&occluder.polygon, &occluder.transform));
+            }
+        }
+    }
+
+    /// Index of the topmost (last drawn) occluder under the point
+    pub fn pick(&self, point: Vec2) -> Option<usize> {
+        self.occluders
+            .iter()
+            .rposition(|occluder| occluder.contains(point))
+    }
+
+    /// Shadow geometry of the light computed by the last `compute_shadows`
+    pub fn shadows(&self, light: usize) -> &[ShadowPoint] {
+        self.shadows.get(light).map_or(&[], |shadows| &shadows[..])
+    }
+
+    /// Part of the world box visible from the point
+    pub fn visibility(&self, point: Vec2) -> Polygon<f32> {
+        let occluders: Vec<_> = self
+            .occluders
+            .iter()
+            .map(Occluder::placed_polygon)
+            .collect();
+        visibility_polygon(point, &occluders, self.bounds)
+    }
+}
+
+#[test]
+fn default_scene_shadows() {
+    let mut world = World::from_scene(&SceneDesc::default_scene());
+    world.compute_shadows();
+    for i in 0..world.lights.len() {
+        assert!(!world.shadows(i).is_empty());
+        assert_eq!(world.shadows(i).len() % 3, 0);
+    }
+    assert!(world.shadows(world.lights.len()).is_empty());
+}
+
+#[test]
+fn shadows_follow_lights() {
+    let mut world = World::from_scene(&SceneDesc::default_scene());
+    world.compute_shadows();
+    world.lights.truncate(1);
+    world.compute_shadows();
+    assert!(!world.shadows(0).is_empty());
+    assert!(world.shadows(1).is_empty());
+}
+
+#[test]
+fn update_moves_only_dynamic_occluders() {
+    let triangle = Polygon::new(vec![(0., 0.), (0.1, 0.), (0., 0.1)].into(), vec![]);
+    let mut world = World {
+        occluders: vec![
+            Occluder {
+                polygon: triangle.clone(),
+                transform: Transform2D::default(),
+                velocity: vec2(0., 0.),
+                angular_velocity: 0.,
+                fixed: false,
+                emission: Vec3::zero(),
+            },
+            Occluder {
+                polygon: triangle,
+                transform: Transform2D::from_translation(vec2(1., 1.)),
+                velocity: vec2(0., 0.),
+                angular_velocity: 1.,
+                fixed: true,
+                emission: Vec3::zero(),
+            },
+        ],
+        ..World::default()
+    };
+    world.update(0.1);
+    assert!(world.occluders[0].transform.translation.y() < 0.);
+    assert_eq!(
+        world.occluders[1].transform,
+        Transform2D::from_translation(vec2(1., 1.))
+    );
+}
+
+#[test]
+fn rotated_occluder_shadows() {
+    let mut world = World::default();
+    world.occluders.push(Occluder {
+        polygon: Polygon::new(
+            vec![(-1., -0.1), (1., -0.1), (1., 0.1), (-1., 0.1)].into(),
+            vec![],
+        ),
+        transform: Transform2D::default(),
+        velocity: vec2(0., 0.),
+        angular_velocity: 0.,
+        fixed: true,
+        emission: Vec3::zero(),
+    });
+    world.lights.push(Light::new(vec2(0., 2.), 0.1));
+    world.compute_shadows();
+    let horizontal = world.shadows(0).to_vec();
+    world.occluders[0].transform.rotation = std::f32::consts::FRAC_PI_2;
+    world.compute_shadows();
+    // vertical plank casts a narrow shadow straight down
+    let spread = |shadows: &[ShadowPoint]| {
+        shadows
+            .iter()
+            .filter(|p| p.pos.y() < -0.5)
+            .map(|p| p.pos.x().abs() / (2. - p.pos.y()))
+            .fold(0., f32::max)
+    };
+    assert!(spread(world.shadows(0)) < 0.2);
+    assert!(spread(&horizontal) > 0.4);
+}
+
+#[test]
+fn visibility_is_bounded() {
+    let world = World::default();
+    let visible = world.visibility(vec2(0., 0.));
+    for p in visible.exterior().points_iter() {
+        assert!(p.x().abs() <= WORLD_HALF_SIZE + 1E-4);
+        assert!(p.y().abs() <= WORLD_HALF_SIZE + 1E-4);
+    }
+}
+
+#[test]
+fn pick_transformed_occluders() {
+    let square = Polygon::new(
+        vec![(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)].into(),
+        vec![],
+    );
+    let occluder = |transform| Occluder {
+        polygon: square.clone(),
+        transform,
+        velocity: vec2(0., 0.),
+        angular_velocity: 0.,
+        fixed: true,
+        emission: Vec3::zero(),
+    };
+    let world = World {
+        occluders: vec![
+            // long thin wall rotated by 45 degrees
+            occluder(Transform2D::new(
+                vec2(0., 0.),
+                std::f32::consts::FRAC_PI_4,
+                vec2(4., 0.2),
+            )),
+            occluder(Transform2D::from_translation(vec2(1., 1.))),
+        ],
+        ..World::default()
+    };
+    assert_eq!(world.pick(vec2(-0.5, -0.5)), Some(0));
+    assert_eq!(world.pick(vec2(1.2, 1.)), Some(1));
+    assert_eq!(world.pick(vec2(1., 0.9)), Some(1));
+    assert_eq!(world.pick(vec2(0.5, -0.5)), None);
+}